@@ -0,0 +1,10 @@
+#version 330 core
+
+in vec3 vertexcolor;
+
+out vec3 color;
+
+
+void main() {
+    color = vertexcolor;
+}
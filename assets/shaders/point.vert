@@ -0,0 +1,14 @@
+#version 330 core
+
+layout(location=0) in vec2 vertexPosition_modelspace;
+layout(location=1) in vec3 incolor;
+// Only u is used, it carries the point size in pixels
+layout(location=2) in vec2 inuv;
+out vec3 vertexcolor;
+
+void main() {
+	gl_Position = vec4(vertexPosition_modelspace, 0, 1);
+	gl_PointSize = inuv.x;
+
+	vertexcolor = incolor;
+}
@@ -0,0 +1,19 @@
+#version 330 core
+
+in vec3 vertexcolor;
+in vec2 uv;
+
+uniform sampler2D tileset;
+uniform int textured;
+
+out vec3 color;
+
+
+void main() {
+    // Without its tileset, a tile is drawn in the flat colour picked for its id
+    if (textured != 0) {
+        color = texture(tileset, uv).rgb;
+    } else {
+        color = vertexcolor;
+    }
+}
@@ -1,4 +1,17 @@
-
+/// What the window tells the game thread about
 pub enum Event {
-    B
-}
\ No newline at end of file
+    /// Stops or resumes the simulation
+    TogglePause,
+    /// Runs a single update while paused
+    Step,
+    /// Doubles how fast simulated time runs
+    SpeedUp,
+    /// Halves how fast simulated time runs
+    SlowDown,
+    /// Back to real time
+    ResetSpeed,
+    /// Writes the world to the snapshot file
+    SaveSnapshot,
+    /// Replaces the world with the one in the snapshot file
+    LoadSnapshot,
+}
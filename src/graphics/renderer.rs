@@ -1,40 +1,201 @@
-use super::{render_caller::RenderCaller, vertex::Vertex, vertex_pack::VertexPack, ShaderIdentifier};
-use crate::logic::{Asset, Position};
+use super::{render_caller::RenderCaller, uniform_data::UniformData, vertex::Vertex, vertex_pack::VertexPack, ShaderIdentifier};
+use crate::logic::{Frame, TileChunk, EMPTY_TILE};
+
+/// Half the length of the triangles rotated entities are drawn as
+const ARROW_SIZE : f32 = 0.02;
+
+/// Size in pixels of the points entities without a rotation are drawn as
+const POINT_SIZE : f32 = 5.0;
+
+/// Buffers 0, 1 and 2 hold the points, the triangles and the particles. Each tilemap chunk gets a
+/// buffer of its own from here on, so it's only uploaded when it changes.
+const FIRST_CHUNK_BUFFER : usize = 3;
+
+/// A tilemap chunk as it was last sent
+struct ChunkMesh {
+    texture : String,
+    /// Kept around for chunks that didn't get a buffer of their own, which share the last buffer
+    /// and are uploaded again every frame
+    overflow : Option<VertexPack>,
+}
 
 pub struct Renderer {
-    render_caller : RenderCaller
+    render_caller : RenderCaller,
+    /// Indexed by chunk. None for chunks without any tiles.
+    chunks : Vec<Option<ChunkMesh>>,
 }
 
 impl Renderer {
     pub unsafe fn new(screen_dimensions : (u32,u32)) -> Self {
         Renderer {
-            render_caller : RenderCaller::new(screen_dimensions)
+            render_caller : RenderCaller::new(screen_dimensions),
+            chunks : Vec::new(),
+        }
+    }
+
+    /// Two triangles per tile, textured with the tile's cell of the tileset.
+    fn chunk_pack(chunk : &TileChunk) -> VertexPack {
+        let mut vertices = Vec::new();
+        let mut elements = Vec::new();
+        let size = chunk.tile_size;
+        for (i, tile) in chunk.tiles.iter().enumerate().filter(|(_, t)| **t != EMPTY_TILE) {
+            let x = chunk.origin.x + (i % chunk.width) as f32 * size;
+            let y = chunk.origin.y + (i / chunk.width) as f32 * size;
+            let cell = (*tile - 1) as u32;
+            let (u, v) = ((cell % chunk.columns) as f32 / chunk.columns as f32, (cell / chunk.columns) as f32 / chunk.rows as f32);
+            let (du, dv) = (1.0 / chunk.columns as f32, 1.0 / chunk.rows as f32);
+            // The first row of the texture is the top of the image
+            let corners = [(0.0, 0.0, u, v + dv), (1.0, 0.0, u + du, v + dv), (1.0, 1.0, u + du, v), (0.0, 1.0, u, v)];
+            let base = vertices.len() as u32;
+            for (cx, cy, cu, cv) in corners.iter() {
+                let mut vertex = Self::vertex(x + cx * size, y + cy * size);
+                vertex.r = ((*tile as u32 * 97) % 256) as f32 / 255.0;
+                vertex.g = ((*tile as u32 * 57) % 256) as f32 / 255.0;
+                vertex.b = ((*tile as u32 * 29) % 256) as f32 / 255.0;
+                vertex.u = *cu;
+                vertex.v = *cv;
+                vertices.push(vertex);
+            }
+            elements.extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+        }
+        VertexPack { vertices, elements }
+    }
+
+    /// The last buffer is shared by the chunks that didn't get their own.
+    fn chunk_buffer(&self, index : usize) -> Option<usize> {
+        let buffer = FIRST_CHUNK_BUFFER + index;
+        if buffer + 1 < self.render_caller.get_vbo_count() { Some(buffer) } else { None }
+    }
+
+    unsafe fn update_chunk(&mut self, chunk : &TileChunk) {
+        if self.chunks.len() <= chunk.index {
+            self.chunks.resize_with(chunk.index + 1, || None);
+        }
+        let pack = Self::chunk_pack(chunk);
+        if pack.vertices.is_empty() {
+            self.chunks[chunk.index] = None;
+            return;
+        }
+        let overflow = match self.chunk_buffer(chunk.index) {
+            Some(buffer) => {
+                self.render_caller.pack(&buffer, &pack);
+                None
+            },
+            None => Some(pack),
+        };
+        self.chunks[chunk.index] = Some(ChunkMesh { texture : chunk.texture.clone(), overflow });
+    }
+
+    /// Tiles whose tileset isn't loaded are drawn in a flat colour instead.
+    unsafe fn render_chunks(&mut self) {
+        if self.chunks.iter().all(|x| x.is_none()) {
+            return;
+        }
+        self.render_caller.choose_shader(ShaderIdentifier::Tile);
+        let shared = self.render_caller.get_vbo_count() - 1;
+        for index in 0..self.chunks.len() {
+            let mesh = match &self.chunks[index] {
+                Some(m) => m,
+                None => continue,
+            };
+            let mut uniforms = UniformData::new();
+            if self.render_caller.get_texture_manager().contains_texture(&mesh.texture) {
+                uniforms.texture(mesh.texture.clone(), "tileset");
+                uniforms.int(1, "textured");
+            } else {
+                uniforms.int(0, "textured");
+            }
+            let buffer = match &mesh.overflow {
+                Some(pack) => {
+                    self.render_caller.pack(&shared, pack);
+                    shared
+                },
+                None => FIRST_CHUNK_BUFFER + index,
+            };
+            self.render_caller.uniforms(&uniforms);
+            self.render_caller.render(&buffer);
         }
     }
 
-    pub unsafe fn render(&mut self, iter : &[(Asset, Position)]) {
-        //println!("Rendering!"); 
+    /// Draws the points in the buffer, each at the size in pixels the point shader reads from u.
+    unsafe fn render_points(&mut self, buffer : usize, vertices : Vec<Vertex>) {
+        if vertices.is_empty() {
+            return;
+        }
+        self.render_caller.pack(&buffer, &VertexPack { vertices, elements : vec![] });
+        self.render_caller.choose_shader(ShaderIdentifier::Point);
+        gl::Enable(gl::PROGRAM_POINT_SIZE);
+        self.render_caller.render(&buffer);
+        gl::Disable(gl::PROGRAM_POINT_SIZE);
+    }
+
+    fn point(x : f32, y : f32, colour : [f32; 3], size : f32) -> Vertex {
+        Vertex {
+            r : colour[0],
+            g : colour[1],
+            b : colour[2],
+            u : size,
+            ..Self::vertex(x, y)
+        }
+    }
+
+    fn vertex(x : f32, y : f32) -> Vertex {
+        Vertex {
+            x : x+0.5,
+            y : y+0.5,
+            r : 1.0,
+            g : 0.0,
+            b : 0.0,
+            u : 0.0,
+            v : 0.0
+        }
+    }
+
+    pub unsafe fn render(&mut self, frame : &Frame) {
+        //println!("Rendering!");
+        for chunk in frame.chunks.iter() {
+            self.update_chunk(chunk);
+        }
+        self.chunks.truncate(frame.chunk_count);
+        let iter = &frame.sprites;
         self.render_caller.clear_buffers(&true, &true);
         let vertices : Vec<Vertex> = iter.iter()
-            .map(|x|
-            
-                Vertex {
-                    x : x.1.x+0.5,
-                    y : x.1.y+0.5,
-                    r : 1.0,
-                    g : 0.0,
-                    b : 0.0,
-                    u : 0.0,
-                    v : 0.0
-                }
-            )
+            .filter(|x| x.rotation.is_none())
+            .map(|x| Self::point(x.position.x, x.position.y, x.asset.colour, x.asset.scale * POINT_SIZE))
             .collect();
+
+        // Rotated entities are triangles pointing along their orientation, so the spin is visible
+        let mut arrows = Vec::new();
+        let mut elements = Vec::new();
+        for sprite in iter {
+            if let Some(angle) = sprite.rotation {
+                let (sin, cos) = angle.sin_cos();
+                let base = arrows.len() as u32;
+                let size = ARROW_SIZE * sprite.asset.scale;
+                let [r, g, b] = sprite.asset.colour;
+                for (x, y) in [(1.0, 0.0), (-0.6, 0.6), (-0.6, -0.6)].iter() {
+                    arrows.push(Vertex { r, g, b, ..Self::vertex(
+                        sprite.position.x + (x * cos - y * sin) * size,
+                        sprite.position.y + (x * sin + y * cos) * size,
+                    ) });
+                }
+                elements.extend_from_slice(&[base, base + 1, base + 2]);
+            }
+        }
+
         //println!("Vertex count: {}", vertices.len());
-        self.render_caller.pack(&0, &VertexPack {
-            vertices,
-            elements : vec![]
-        });
+        self.render_points(0, vertices);
         self.render_caller.choose_shader(ShaderIdentifier::Default);
-        self.render_caller.render(&0);
+        if !arrows.is_empty() {
+            self.render_caller.pack(&1, &VertexPack {
+                vertices : arrows,
+                elements
+            });
+            self.render_caller.render(&1);
+        }
+        let particles = frame.particles.iter().map(|x| Self::point(x.position.x, x.position.y, x.colour, x.size)).collect();
+        self.render_points(2, particles);
+        // Last, so the depth test keeps the entities and particles on top of the tiles
+        self.render_chunks();
     }
-}
\ No newline at end of file
+}
@@ -20,17 +20,23 @@ pub struct Shader {
 #[derive(Clone, Copy, Debug, EnumCount, EnumIter)]
 pub enum ShaderIdentifier {
     Default,
+    Tile,
+    Point,
 }
 
 impl ShaderIdentifier {
     pub fn name(&self) -> &'static str{
         match self {
-            ShaderIdentifier::Default => "Default"
+            ShaderIdentifier::Default => "Default",
+            ShaderIdentifier::Tile => "Tile",
+            ShaderIdentifier::Point => "Point",
         }
     }
     pub fn extensionless_path(&self) -> &'static str{
         match self {
-            ShaderIdentifier::Default => "shaders/default"
+            ShaderIdentifier::Default => "shaders/default",
+            ShaderIdentifier::Tile => "shaders/tile",
+            ShaderIdentifier::Point => "shaders/point",
         }
     }
 
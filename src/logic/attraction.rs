@@ -0,0 +1,256 @@
+
+use super::{CompFlag, EntityId, Game};
+
+/// Deeper than this, bodies sitting on top of each other share a leaf instead of splitting it forever
+const MAX_DEPTH : usize = 32;
+
+/// Newtonian attraction between every pair of entities with a mass, approximated with a Barnes-Hut quadtree.
+#[derive(Debug, Clone)]
+pub struct Attraction {
+    pub gravitational_constant : f32,
+    /// Opening angle. A node whose width over its distance is below it pulls as a single body at
+    /// its centre of mass. 0 makes the tree exact, larger values trade accuracy for speed.
+    pub theta : f32,
+    /// Added to every distance, so close encounters don't fling bodies apart
+    pub softening : f32,
+}
+
+impl Default for Attraction {
+    fn default() -> Self {
+        Self {
+            gravitational_constant : 1.0,
+            theta : 0.5,
+            softening : 0.01,
+        }
+    }
+}
+
+impl Attraction {
+    /// Acceleration a body at position gets from a mass at source.
+    fn pull(&self, position : &glm::Vec2, source : &glm::Vec2, mass : f32) -> glm::Vec2 {
+        let d = source - position;
+        let distance_squared = d.norm_squared() + self.softening * self.softening;
+        d * (self.gravitational_constant * mass / (distance_squared * distance_squared.sqrt()))
+    }
+}
+
+struct Node {
+    centre : glm::Vec2,
+    half_size : f32,
+    count : usize,
+    mass : f32,
+    /// Positions weighted by mass, summed. Divided by the mass, it's the centre of mass.
+    moment : glm::Vec2,
+    /// Only meaningful while count is 1
+    body : Option<(EntityId, glm::Vec2, f32)>,
+    /// Index of the first of four consecutive children, ordered by quadrant
+    children : Option<usize>,
+}
+
+impl Node {
+    fn new(centre : glm::Vec2, half_size : f32) -> Self {
+        Self { centre, half_size, count : 0, mass : 0.0, moment : glm::Vec2::zeros(), body : None, children : None }
+    }
+
+    fn quadrant(&self, position : &glm::Vec2) -> usize {
+        (position.x >= self.centre.x) as usize + 2 * (position.y >= self.centre.y) as usize
+    }
+
+    fn add(&mut self, position : &glm::Vec2, mass : f32) {
+        self.count += 1;
+        self.mass += mass;
+        self.moment += position * mass;
+    }
+}
+
+/// Every body with a mass, bucketed so each node knows the total mass and centre of mass below it.
+pub struct QuadTree {
+    nodes : Vec<Node>,
+}
+
+impl QuadTree {
+    pub fn build(bodies : &[(EntityId, glm::Vec2, f32)]) -> Self {
+        let mut min = glm::vec2(f32::INFINITY, f32::INFINITY);
+        let mut max = glm::vec2(f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for (_, p, _) in bodies {
+            min = glm::min2(&min, p);
+            max = glm::max2(&max, p);
+        }
+        let (centre, half_size) = if bodies.is_empty() {
+            (glm::Vec2::zeros(), 1.0)
+        } else {
+            ((min + max) / 2.0, ((max - min).max() / 2.0).max(f32::EPSILON) * 1.001)
+        };
+
+        let mut tree = QuadTree { nodes : vec![Node::new(centre, half_size)] };
+        for body in bodies {
+            tree.insert(*body);
+        }
+        tree
+    }
+
+    fn insert(&mut self, body : (EntityId, glm::Vec2, f32)) {
+        let (_, position, mass) = body;
+        let mut node = 0;
+        for depth in 0.. {
+            self.nodes[node].add(&position, mass);
+            if self.nodes[node].count == 1 {
+                self.nodes[node].body = Some(body);
+                return;
+            }
+            if self.nodes[node].children.is_none() {
+                if depth >= MAX_DEPTH {
+                    return;
+                }
+                self.split(node);
+            }
+            node = self.nodes[node].children.unwrap() + self.nodes[node].quadrant(&position);
+        }
+    }
+
+    /// Gives a leaf four children, and moves the body it held into the right one.
+    fn split(&mut self, node : usize) {
+        let first = self.nodes.len();
+        let (centre, half) = (self.nodes[node].centre, self.nodes[node].half_size / 2.0);
+        for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+            self.nodes.push(Node::new(centre + glm::vec2(x, y) * half, half));
+        }
+        self.nodes[node].children = Some(first);
+        if let Some(body) = self.nodes[node].body.take() {
+            let child = first + self.nodes[node].quadrant(&body.1);
+            self.nodes[child].add(&body.1, body.2);
+            self.nodes[child].body = Some(body);
+        }
+    }
+
+    /// Acceleration of the body with the given id at position, from everything else in the tree.
+    pub fn acceleration(&self, id : EntityId, position : &glm::Vec2, attraction : &Attraction) -> glm::Vec2 {
+        let mut acc = glm::Vec2::zeros();
+        let mut stack = vec![0];
+        while let Some(i) = stack.pop() {
+            let node = &self.nodes[i];
+            if node.count == 0 || node.mass == 0.0 {
+                continue;
+            }
+            if node.count == 1 {
+                let (other, source, mass) = node.body.unwrap();
+                if other != id {
+                    acc += attraction.pull(position, &source, mass);
+                }
+                continue;
+            }
+            let centre_of_mass = node.moment / node.mass;
+            match node.children {
+                Some(first) if 2.0 * node.half_size >= attraction.theta * (centre_of_mass - position).norm() => {
+                    stack.extend(first..first + 4);
+                },
+                // Far enough away, or bodies on top of each other at the bottom of the tree
+                _ => acc += attraction.pull(position, &centre_of_mass, node.mass),
+            }
+        }
+        acc
+    }
+}
+
+impl Game {
+    fn attracting_bodies(&self) -> Vec<(EntityId, glm::Vec2, f32)> {
+        self.entities.iter()
+            .filter(|x| x.components.contains(CompFlag::Pos | CompFlag::Mas))
+            .map(|x| {
+                let pos = &self.positions[x.id as usize];
+                (x.id, glm::vec2(pos.x, pos.y), self.masses[x.id as usize].value)
+            })
+            .collect()
+    }
+
+    /// Acceleration of every body with a mass towards all the others, from the quadtree, indexed by entity id.
+    pub fn approximate_attraction(&self) -> Vec<glm::Vec2> {
+        let mut acc = vec![glm::Vec2::zeros(); self.entities.len()];
+        let attraction = match &self.attraction {
+            Some(a) => a,
+            None => return acc,
+        };
+        let bodies = self.attracting_bodies();
+        let tree = QuadTree::build(&bodies);
+        for &(id, position, _) in bodies.iter() {
+            acc[id as usize] = tree.acceleration(id, &position, attraction);
+        }
+        acc
+    }
+
+    /// Adds the attraction to the force of every moving body with a mass, and returns the
+    /// acceleration it added by entity id. Empty without an attraction.
+    /// Static and sleeping bodies still pull on the others.
+    pub(super) fn attract(&mut self) -> Vec<glm::Vec2> {
+        if self.attraction.is_none() {
+            return Vec::new();
+        }
+        let acc = self.approximate_attraction();
+        for entity in self.entities.iter().filter(|x| x.components.contains(CompFlag::Pos | CompFlag::Vel | CompFlag::Mas) && !x.components.contains(CompFlag::Sta)) {
+            let id = entity.id as usize;
+            self.forces[id].x += acc[id].x * self.masses[id].value;
+            self.forces[id].y += acc[id].y * self.masses[id].value;
+        }
+        acc
+    }
+
+    /// Same as approximate_attraction, but summed pair by pair in O(n²). For checking how far the tree strays
+    /// from the exact forces on small sets.
+    pub fn exact_attraction(&self) -> Vec<glm::Vec2> {
+        let mut acc = vec![glm::Vec2::zeros(); self.entities.len()];
+        let attraction = match &self.attraction {
+            Some(a) => a,
+            None => return acc,
+        };
+        let bodies = self.attracting_bodies();
+        for &(id, position, _) in bodies.iter() {
+            for &(other, source, mass) in bodies.iter() {
+                if other != id {
+                    acc[id as usize] += attraction.pull(&position, &source, mass);
+                }
+            }
+        }
+        acc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::Position;
+    use super::*;
+
+    /// A cluster of bodies scattered by a fixed LCG, with masses between 1 and 2
+    fn cluster(theta : f32) -> Game {
+        let mut game = Game::new();
+        game.attraction = Some(Attraction { theta, ..Attraction::default() });
+        let mut state = 12_345u32;
+        let mut next = || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1u32 << 24) as f32
+        };
+        for _ in 0..200 {
+            let id = game.add_entity(CompFlag::Pos | CompFlag::Mas) as usize;
+            game.positions[id] = Position { x : next() * 2.0 - 1.0, y : next() * 2.0 - 1.0 };
+            game.masses[id].value = 1.0 + next();
+        }
+        game
+    }
+
+    /// Root mean square error of the tree's accelerations, relative to the size of the exact ones
+    fn relative_error(game : &Game) -> f32 {
+        let (approximate, exact) = (game.approximate_attraction(), game.exact_attraction());
+        let error : f32 = approximate.iter().zip(&exact).map(|(a, e)| (a - e).norm_squared()).sum();
+        let size : f32 = exact.iter().map(|e| e.norm_squared()).sum();
+        (error / size).sqrt()
+    }
+
+    #[test]
+    fn barnes_hut_strays_from_the_exact_forces_by_about_theta_squared() {
+        for theta in [0.0, 0.3, 0.5, 0.8, 1.0] {
+            let error = relative_error(&cluster(theta));
+            // At theta 0 every node gets opened, which leaves just float rounding
+            let tolerance = 0.05 * theta * theta + 1e-5;
+            assert!(error < tolerance, "theta {} strayed by {}, more than {}", theta, error, tolerance);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+
+use super::{CompFlag, EntityId, Game};
+
+/// What happens to an entity that leaves the world bounds
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+pub enum BoundaryPolicy {
+    /// Comes back in on the opposite side
+    Wrap,
+    /// Mirrored back inside, with the velocity across the edge reversed
+    Bounce,
+    /// Held at the edge, losing the velocity across it
+    #[default]
+    Clamp,
+    Despawn,
+}
+
+pub struct WorldBounds {
+    pub min : glm::Vec2,
+    pub max : glm::Vec2,
+    /// Used for entities without a boundary policy of their own
+    pub policy : BoundaryPolicy,
+}
+
+impl WorldBounds {
+    pub fn new(min : glm::Vec2, max : glm::Vec2, policy : BoundaryPolicy) -> Self {
+        Self { min, max, policy }
+    }
+}
+
+impl Game {
+    /// Brings every moving entity that left the world bounds back in line with its policy.
+    pub(super) fn apply_bounds(&mut self) {
+        let bounds = match &self.bounds {
+            Some(b) => b,
+            None => return,
+        };
+        let size = bounds.max - bounds.min;
+
+        let mut despawned : Vec<EntityId> = Vec::new();
+        for entity in self.entities.iter().filter(|x| x.components.contains(CompFlag::Pos | CompFlag::Vel) && !x.components.intersects(CompFlag::Sta | CompFlag::Slp)) {
+            let id = entity.id as usize;
+            let policy = if entity.components.contains(CompFlag::Bnd) { self.boundary_policies[id] } else { bounds.policy };
+            let pos = &mut self.positions[id];
+            let vel = &mut self.velocities[id];
+            for (p, v, min, max, size) in [(&mut pos.x, &mut vel.x, bounds.min.x, bounds.max.x, size.x), (&mut pos.y, &mut vel.y, bounds.min.y, bounds.max.y, size.y)] {
+                if *p >= min && *p <= max {
+                    continue;
+                }
+                match policy {
+                    BoundaryPolicy::Wrap => *p = min + (*p - min).rem_euclid(size),
+                    BoundaryPolicy::Bounce => {
+                        // Heading back inwards, even if something already turned it around
+                        if *p < min {
+                            *p = 2.0 * min - *p;
+                            *v = v.abs();
+                        } else {
+                            *p = 2.0 * max - *p;
+                            *v = -v.abs();
+                        }
+                        *p = p.max(min).min(max);
+                    },
+                    BoundaryPolicy::Clamp => {
+                        *p = p.max(min).min(max);
+                        *v = 0.0;
+                    },
+                    BoundaryPolicy::Despawn => {
+                        despawned.push(entity.id);
+                        break;
+                    },
+                }
+            }
+        }
+        for id in despawned {
+            self.despawn(id);
+        }
+    }
+}
@@ -0,0 +1,266 @@
+
+use super::{grid::EntityGrid, Collider, EntityId, Position};
+use super::snapshot::{unknown, Reader, Save, Writer};
+
+/// Picks how Game::collide finds the pairs of colliders that might touch.
+pub enum Broadphase {
+    /// Pairs up colliders sorted into neighbouring cells of the game's EntityGrid.
+    /// Cheap, but only finds everything when colliders are no larger than a cell.
+    Grid,
+    /// Sorts collider bounds along one axis and sweeps over them. Handles colliders of any size and clustering.
+    SweepAndPrune(SweepAndPrune),
+}
+
+impl Broadphase {
+    /// Candidate pairs among the given colliding entities, each with the lower id first.
+    /// Resting entities, static or asleep, are only paired up with awake ones.
+    pub fn find_pairs(&mut self, awake : &[EntityId], resting : &[EntityId], grid : &EntityGrid, static_grid : &EntityGrid, positions : &[Position], colliders : &[Collider]) -> Vec<(EntityId, EntityId)> {
+        match self {
+            Broadphase::Grid => grid_pairs(awake, resting, grid, static_grid, positions),
+            Broadphase::SweepAndPrune(sap) => sap.find_pairs(awake, resting, positions, colliders),
+        }
+    }
+}
+
+impl Broadphase {
+    /// The entities the broadphase keeps between updates.
+    pub(super) fn entity_ids(&self) -> &[EntityId] {
+        match self {
+            Broadphase::Grid => &[],
+            Broadphase::SweepAndPrune(sap) => &sap.order,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Role {
+    None,
+    Awake,
+    Resting,
+}
+
+/// Static entities are looked up in the static grid, which has to have the same cells as grid.
+fn grid_pairs(awake : &[EntityId], resting : &[EntityId], grid : &EntityGrid, static_grid : &EntityGrid, positions : &[Position]) -> Vec<(EntityId, EntityId)> {
+    let mut roles = vec![Role::None; positions.len()];
+    for id in awake {
+        roles[*id as usize] = Role::Awake;
+    }
+    for id in resting {
+        roles[*id as usize] = Role::Resting;
+    }
+
+    let mut pairs = Vec::new();
+    for &id in awake {
+        let cell = grid.get_location(&positions[id as usize]);
+        for cx in (cell.0 - 1)..=(cell.0 + 1) {
+            for cy in (cell.1 - 1)..=(cell.1 + 1) {
+                for g in [grid, static_grid] {
+                    if let Some(nearby) = g.find_in_cell(&(cx, cy)) {
+                        for &other in nearby.iter() {
+                            match roles[other as usize] {
+                                Role::Awake if other > id => pairs.push((id, other)),
+                                Role::Resting => pairs.push((id.min(other), id.max(other))),
+                                _ => (),
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+    pairs
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SweepAxis {
+    X,
+    Y,
+}
+
+/// Sort and sweep broadphase. Entities stay sorted between frames, so the insertion sort
+/// that fixes up the order each frame is close to linear when things move smoothly.
+pub struct SweepAndPrune {
+    axis : SweepAxis,
+    /// Entities sorted by the lower edge of their bounds along the sweep axis
+    order : Vec<EntityId>,
+    /// Bounds indexed by entity id. Lower and upper edge along the sweep axis, then along the other axis.
+    bounds : Vec<[f32; 4]>,
+    in_order : Vec<bool>,
+    present : Vec<bool>,
+    resting : Vec<bool>,
+}
+
+impl SweepAndPrune {
+    pub fn new(axis : SweepAxis) -> Self {
+        Self {
+            axis,
+            order : Vec::new(),
+            bounds : Vec::new(),
+            in_order : Vec::new(),
+            present : Vec::new(),
+            resting : Vec::new(),
+        }
+    }
+
+    fn update_bounds(&mut self, awake : &[EntityId], resting : &[EntityId], positions : &[Position], colliders : &[Collider]) {
+        let len = positions.len();
+        self.bounds.resize(len, [0.0; 4]);
+        self.in_order.resize(len, false);
+        self.present.clear();
+        self.present.resize(len, false);
+        self.resting.clear();
+        self.resting.resize(len, false);
+
+        for &id in resting {
+            self.resting[id as usize] = true;
+        }
+        for &id in awake.iter().chain(resting) {
+            let pos = &positions[id as usize];
+            let (hw, hh) = colliders[id as usize].half_extents();
+            self.bounds[id as usize] = match self.axis {
+                SweepAxis::X => [pos.x - hw, pos.x + hw, pos.y - hh, pos.y + hh],
+                SweepAxis::Y => [pos.y - hh, pos.y + hh, pos.x - hw, pos.x + hw],
+            };
+            self.present[id as usize] = true;
+        }
+
+        // Drop entities that stopped colliding, and append new ones for the sort to place.
+        let present = &self.present;
+        let in_order = &mut self.in_order;
+        self.order.retain(|id| {
+            in_order[*id as usize] = present[*id as usize];
+            present[*id as usize]
+        });
+        for &id in awake.iter().chain(resting) {
+            if !self.in_order[id as usize] {
+                self.in_order[id as usize] = true;
+                self.order.push(id);
+            }
+        }
+    }
+
+    fn insertion_sort(&mut self) {
+        let bounds = &self.bounds;
+        for i in 1..self.order.len() {
+            let mut j = i;
+            while j > 0 && bounds[self.order[j - 1] as usize][0] > bounds[self.order[j] as usize][0] {
+                self.order.swap(j - 1, j);
+                j -= 1;
+            }
+        }
+    }
+
+    pub fn find_pairs(&mut self, awake : &[EntityId], resting : &[EntityId], positions : &[Position], colliders : &[Collider]) -> Vec<(EntityId, EntityId)> {
+        self.update_bounds(awake, resting, positions, colliders);
+        self.insertion_sort();
+
+        let mut pairs = Vec::new();
+        for (i, &a) in self.order.iter().enumerate() {
+            let ba = &self.bounds[a as usize];
+            for &b in self.order[i + 1..].iter() {
+                let bb = &self.bounds[b as usize];
+                if bb[0] > ba[1] {
+                    break;
+                }
+                if bb[2] <= ba[3] && ba[2] <= bb[3] && !(self.resting[a as usize] && self.resting[b as usize]) {
+                    pairs.push((a.min(b), a.max(b)));
+                }
+            }
+        }
+        pairs
+    }
+}
+
+/// The sweep order is saved, since pairs come out in it. Bounds are worked out again every update.
+impl Save for Broadphase {
+    fn save(&self, w : &mut Writer) {
+        match self {
+            Broadphase::Grid => w.tag(0),
+            Broadphase::SweepAndPrune(sap) => {
+                w.tag(1);
+                sap.axis.save(w);
+                sap.order.save(w);
+            },
+        }
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        match r.tag()? {
+            0 => Ok(Broadphase::Grid),
+            1 => {
+                let mut sap = SweepAndPrune::new(Save::load(r)?);
+                sap.order = Save::load(r)?;
+                for &id in &sap.order {
+                    if sap.in_order.len() <= id as usize {
+                        sap.in_order.resize(id as usize + 1, false);
+                    }
+                    sap.in_order[id as usize] = true;
+                }
+                Ok(Broadphase::SweepAndPrune(sap))
+            },
+            tag => unknown("broadphase", tag),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use super::super::Shape;
+    use super::*;
+
+    fn overlapping(a : EntityId, b : EntityId, positions : &[Position], colliders : &[Collider]) -> bool {
+        let (pa, pb) = (&positions[a as usize], &positions[b as usize]);
+        let (wa, ha) = colliders[a as usize].half_extents();
+        let (wb, hb) = colliders[b as usize].half_extents();
+        (pa.x - pb.x).abs() <= wa + wb && (pa.y - pb.y).abs() <= ha + hb
+    }
+
+    #[test]
+    fn sweep_and_prune_finds_the_overlapping_pairs_the_grid_does() {
+        let mut state = 7u32;
+        let mut next = || {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (state >> 8) as f32 / (1u32 << 24) as f32
+        };
+        let count = 150;
+        let mut positions : Vec<Position> = (0..count).map(|_| Position { x : next(), y : next() }).collect();
+        let colliders : Vec<Collider> = (0..count)
+            .map(|_| Collider { shape : Shape::Circle { radius : 0.01 + 0.03 * next() }, ..Collider::default() })
+            .collect();
+        let awake : Vec<EntityId> = (0..count as EntityId).filter(|id| id % 4 != 0).collect();
+        let resting : Vec<EntityId> = (0..count as EntityId).filter(|id| id % 4 == 0).collect();
+
+        let mut sap = SweepAndPrune::new(SweepAxis::X);
+        let (mut grid, mut static_grid) = (EntityGrid::new(0.1), EntityGrid::new(0.1));
+        for update in 0..5 {
+            // Everything awake moves far enough to shuffle the sweep order
+            if update > 0 {
+                for &id in &awake {
+                    let pos = &mut positions[id as usize];
+                    pos.x = (pos.x + (next() - 0.5) * 0.2).clamp(0.0, 1.0);
+                    pos.y = (pos.y + (next() - 0.5) * 0.2).clamp(0.0, 1.0);
+                }
+            }
+            for &id in &awake {
+                grid.sort_single(id, &positions[id as usize]);
+            }
+            for &id in &resting {
+                static_grid.sort_single(id, &positions[id as usize]);
+            }
+
+            let swept : Vec<(EntityId, EntityId)> = sap.find_pairs(&awake, &resting, &positions, &colliders);
+            let swept_set : BTreeSet<(EntityId, EntityId)> = swept.iter().copied().collect();
+            assert_eq!(swept.len(), swept_set.len(), "Sweep and prune found a pair twice");
+            assert!(swept.iter().all(|&(a, b)| a < b && overlapping(a, b, &positions, &colliders)));
+
+            let gridded : BTreeSet<(EntityId, EntityId)> = grid_pairs(&awake, &resting, &grid, &static_grid, &positions)
+                .into_iter()
+                .filter(|&(a, b)| overlapping(a, b, &positions, &colliders))
+                .collect();
+            assert!(!gridded.is_empty());
+            assert_eq!(swept_set, gridded, "Pairs differ after update {}", update);
+        }
+    }
+}
@@ -0,0 +1,73 @@
+
+use super::{CompFlag, EntityId, Game, Position, QueryFilter};
+
+/// How far past the time of impact a swept entity is placed, so the narrow phase sees the contact and responds to it.
+const CONTACT_SKIN : f32 = 1e-4;
+
+impl Game {
+    /// Sweeps every entity flagged for continuous collision detection from its current position to where
+    /// integration moved it, and returns the position each one that hits something should be clamped to instead.
+    /// Boxes are swept as the circle around their larger half extent.
+    /// Has to run before the integrated positions are written, since the sweep starts from the current positions.
+    pub(super) fn sweep_fast_movers<'a>(&self, moved : impl Iterator<Item = (EntityId, &'a Position)>) -> Vec<(EntityId, Position)> {
+        moved
+            .filter(|(i, _)| self.entities[*i as usize].components.contains(CompFlag::Pos | CompFlag::Vel | CompFlag::Col | CompFlag::Ccd))
+            .filter_map(|(i, target)| {
+                let id = i as usize;
+                let pos = &self.positions[id];
+                let step = glm::vec2(target.x - pos.x, target.y - pos.y);
+                let distance = step.norm();
+                if distance == 0.0 {
+                    return None;
+                }
+                let (hw, hh) = self.colliders[id].half_extents();
+                // Sensors don't stop anything, so they're swept straight through
+                let filter = QueryFilter {
+                    excluded : CompFlag::Sen,
+                    layers : self.colliders[id].layers,
+                    exclude : Some(i),
+                    ..QueryFilter::default()
+                };
+                // Contacts the entity is already moving out of don't stop it
+                let hit = self.shape_cast_all(&self.positions[id], hw.max(hh), step, distance, &filter)
+                    .into_iter()
+                    .find(|hit| hit.normal.dot(&step) < 0.0)?;
+                let clamped = (hit.distance + CONTACT_SKIN).min(distance);
+                Some((i, Position {
+                    x : pos.x + step.x / distance * clamped,
+                    y : pos.y + step.y / distance * clamped,
+                }))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Collider, Shape, Velocity};
+    use super::*;
+
+    #[test]
+    fn bullets_pass_through_sensors_and_stop_at_thin_walls() {
+        let mut game = Game::new();
+        let bullet = game.add_entity(CompFlag::Pos | CompFlag::Vel | CompFlag::Col | CompFlag::Ccd);
+        game.positions[bullet as usize] = Position { x : -0.5, y : 0.0 };
+        game.velocities[bullet as usize] = Velocity { x : 30.0, y : 0.0 };
+        game.colliders[bullet as usize].shape = Shape::Circle { radius : 0.01 };
+        let sensor = game.add_entity(CompFlag::Pos | CompFlag::Col | CompFlag::Sen);
+        game.colliders[sensor as usize].shape = Shape::Aabb { half_width : 0.05, half_height : 0.05 };
+        let wall = game.add_entity(CompFlag::Pos | CompFlag::Col);
+        game.positions[wall as usize] = Position { x : 0.5, y : 0.0 };
+        game.colliders[wall as usize] = Collider { shape : Shape::Aabb { half_width : 0.005, half_height : 0.5 }, restitution : 0.0, ..Collider::default() };
+
+        let (mut tx, _rx) = std::sync::mpsc::sync_channel(1);
+        game.update(&mut tx);
+        game.update(&mut tx);
+        assert!(game.positions[bullet as usize].x > 0.05, "Bullet stalled in the sensor at {}", game.positions[bullet as usize].x);
+        for _ in 0..10 {
+            game.update(&mut tx);
+        }
+        let x = game.positions[bullet as usize].x;
+        assert!(x < 0.495 && x > 0.4, "Bullet should rest against the wall, is at {}", x);
+    }
+}
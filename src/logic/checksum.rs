@@ -0,0 +1,207 @@
+
+use std::collections::VecDeque;
+
+use super::{Game, Shape};
+
+/// Mixes numbers into one, FxHash style. Floats go in by their bits, so a checksum only matches
+/// when every value matches exactly.
+pub(super) struct Checksum {
+    hash : u64,
+}
+
+impl Checksum {
+    fn new() -> Self {
+        Self { hash : 0 }
+    }
+
+    pub fn u64(&mut self, value : u64) {
+        self.hash = (self.hash.rotate_left(5) ^ value).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+
+    pub fn u32(&mut self, value : u32) {
+        self.u64(value as u64);
+    }
+
+    pub fn usize(&mut self, value : usize) {
+        self.u64(value as u64);
+    }
+
+    pub fn f32(&mut self, value : f32) {
+        self.u32(value.to_bits());
+    }
+
+    pub fn vec2(&mut self, value : &glm::Vec2) {
+        self.f32(value.x);
+        self.f32(value.y);
+    }
+
+    pub fn colour(&mut self, value : &[f32; 3]) {
+        for c in value {
+            self.f32(*c);
+        }
+    }
+
+    /// Options get a marker first, so None and Some(0) don't collide.
+    pub fn option(&mut self, value : Option<u64>) {
+        match value {
+            Some(value) => {
+                self.u64(1);
+                self.u64(value);
+            },
+            None => self.u64(0),
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+}
+
+/// Checksums of the world after recent updates, by tick, for finding the first tick two runs
+/// that should have matched went apart. Only the latest capacity ticks are kept.
+#[derive(Debug, Clone)]
+pub struct ChecksumLog {
+    pub capacity : usize,
+    entries : VecDeque<(u64, u64)>,
+}
+
+impl ChecksumLog {
+    pub fn new(capacity : usize) -> Self {
+        Self { capacity, entries : VecDeque::new() }
+    }
+
+    fn record(&mut self, tick : u64, checksum : u64) {
+        self.entries.push_back((tick, checksum));
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    /// The last tick recorded and its checksum
+    pub fn latest(&self) -> Option<(u64, u64)> {
+        self.entries.back().copied()
+    }
+
+    /// The checksum after the given tick, if it's still kept.
+    pub fn get(&self, tick : u64) -> Option<u64> {
+        let first = self.entries.front()?.0;
+        let (recorded, checksum) = *self.entries.get(tick.checked_sub(first)? as usize)?;
+        if recorded == tick { Some(checksum) } else { None }
+    }
+
+    /// The earliest tick both logs kept whose checksums differ. None if all of those match.
+    pub fn first_divergence(&self, other : &ChecksumLog) -> Option<u64> {
+        self.entries.iter()
+            .find(|(tick, checksum)| other.get(*tick).is_some_and(|x| x != *checksum))
+            .map(|(tick, _)| *tick)
+    }
+}
+
+impl Game {
+    /// Sums up everything that changes as the world is simulated: the tick, every entity's flags
+    /// and components, constraints, particles and the random numbers handed out. Configuration,
+    /// like the optional systems, prefabs and machines, isn't part of it.
+    pub fn checksum(&self) -> u64 {
+        let mut sum = Checksum::new();
+        sum.u64(self.tick);
+        sum.usize(self.entities.len());
+        for entity in &self.entities {
+            let i = entity.id as usize;
+            sum.u32(entity.components.bits());
+
+            let (pos, vel) = (&self.positions[i], &self.velocities[i]);
+            sum.f32(pos.x);
+            sum.f32(pos.y);
+            sum.f32(vel.x);
+            sum.f32(vel.y);
+            sum.f32(self.orientations[i].angle);
+            sum.f32(self.angular_velocities[i].w);
+            let force = &self.forces[i];
+            sum.f32(force.x);
+            sum.f32(force.y);
+            sum.f32(force.torque);
+            sum.f32(self.masses[i].value);
+            sum.f32(self.densities[i]);
+
+            let asset = &self.assets[i];
+            sum.colour(&asset.colour);
+            sum.f32(asset.scale);
+
+            let collider = &self.colliders[i];
+            match &collider.shape {
+                Shape::Circle { radius } => {
+                    sum.u32(0);
+                    sum.f32(*radius);
+                },
+                Shape::Aabb { half_width, half_height } => {
+                    sum.u32(1);
+                    sum.f32(*half_width);
+                    sum.f32(*half_height);
+                },
+                Shape::Polygon(polygon) => {
+                    sum.u32(2);
+                    for vertex in polygon.vertices() {
+                        sum.vec2(vertex);
+                    }
+                },
+            }
+            sum.u32(collider.layers);
+            sum.f32(collider.restitution);
+            sum.f32(collider.friction);
+
+            let inside = &self.sensors[i].inside;
+            sum.usize(inside.len());
+            for id in inside {
+                sum.u32(*id as u32);
+            }
+            sum.u32(self.boundary_policies[i] as u32);
+            let rest = &self.rests[i];
+            sum.f32(rest.anchor.x);
+            sum.f32(rest.anchor.y);
+            sum.f32(rest.time);
+
+            self.steerings[i].digest(&mut sum);
+            self.emitters[i].digest(&mut sum);
+            let lifetime = &self.lifetimes[i];
+            sum.f32(lifetime.remaining);
+            sum.option(lifetime.on_expire.map(|x| x as u64));
+            sum.f32(self.tweenings[i].elapsed);
+            let fsm = &self.state_machines[i];
+            sum.option(fsm.current().map(|x| x as u64));
+            sum.f32(fsm.time_in_state());
+            self.spawners[i].digest(&mut sum);
+            sum.option(self.spawned_by[i].map(|x| x as u64));
+        }
+        sum.usize(self.free_ids.len());
+        for id in &self.free_ids {
+            sum.u32(*id as u32);
+        }
+        sum.usize(self.constraints.len());
+        for constraint in &self.constraints {
+            sum.u32(constraint.a as u32);
+            sum.u32(constraint.b as u32);
+            sum.f32(constraint.rest_length);
+        }
+        sum.usize(self.particles.len());
+        for particle in self.particles.iter() {
+            sum.vec2(&particle.position);
+            sum.vec2(&particle.velocity);
+            sum.f32(particle.age);
+            sum.f32(particle.lifetime);
+        }
+        self.random.digest(&mut sum);
+        sum.finish()
+    }
+
+    /// Counts the update and records its checksum, when the game keeps a log of them.
+    pub(super) fn finish_tick(&mut self) {
+        self.tick += 1;
+        if self.checksums.is_some() {
+            let checksum = self.checksum();
+            let tick = self.tick;
+            if let Some(log) = &mut self.checksums {
+                log.record(tick, checksum);
+            }
+        }
+    }
+}
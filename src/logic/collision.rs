@@ -0,0 +1,317 @@
+
+use super::{polygon::{Polygon, WorldPolygon}, Position, Shape};
+
+/// Where and how deep two colliders overlap.
+#[derive(Debug, Clone)]
+pub struct Contact {
+    /// Unit normal pointing from the first collider towards the second
+    pub normal : glm::Vec2,
+    pub depth : f32,
+    /// World space points the two colliders touch at. Two when faces lie against each other, otherwise one.
+    points : [glm::Vec2; 2],
+    point_count : usize,
+}
+
+impl Contact {
+    fn single(normal : glm::Vec2, depth : f32, point : glm::Vec2) -> Self {
+        Contact { normal, depth, points : [point, point], point_count : 1 }
+    }
+
+    fn flipped(self) -> Self {
+        Contact { normal : -self.normal, ..self }
+    }
+
+    /// The contact manifold
+    pub fn points(&self) -> &[glm::Vec2] {
+        &self.points[..self.point_count]
+    }
+}
+
+/// Narrow phase test between two placed shapes. The angles only matter for polygons; boxes stay axis aligned.
+pub fn contact(a_pos : &Position, a_angle : f32, a : &Shape, b_pos : &Position, b_angle : f32, b : &Shape) -> Option<Contact> {
+    let pa = glm::vec2(a_pos.x, a_pos.y);
+    let pb = glm::vec2(b_pos.x, b_pos.y);
+    match (a, b) {
+        (Shape::Circle { radius : ra }, Shape::Circle { radius : rb }) => circle_circle(&pa, *ra, &pb, *rb),
+        (Shape::Circle { radius }, Shape::Aabb { half_width, half_height }) => circle_box(&pa, *radius, &pb, *half_width, *half_height),
+        (Shape::Aabb { half_width, half_height }, Shape::Circle { radius }) => circle_box(&pb, *radius, &pa, *half_width, *half_height).map(Contact::flipped),
+        (Shape::Aabb { half_width : wa, half_height : ha }, Shape::Aabb { half_width : wb, half_height : hb }) => box_box(&pa, *wa, *ha, &pb, *wb, *hb),
+        (Shape::Polygon(polygon), Shape::Circle { radius }) => polygon_circle(&polygon.transformed(&pa, a_angle), &pb, *radius),
+        (Shape::Circle { radius }, Shape::Polygon(polygon)) => polygon_circle(&polygon.transformed(&pb, b_angle), &pa, *radius).map(Contact::flipped),
+        (Shape::Polygon(_), _) | (_, Shape::Polygon(_)) => polygon_polygon(&as_polygon(a, &pa, a_angle), &as_polygon(b, &pb, b_angle)),
+    }
+}
+
+/// Boxes take part in polygon tests as rectangles that don't rotate.
+fn as_polygon(shape : &Shape, position : &glm::Vec2, angle : f32) -> WorldPolygon {
+    match shape {
+        Shape::Polygon(polygon) => polygon.transformed(position, angle),
+        Shape::Aabb { half_width, half_height } => Polygon::rectangle(*half_width, *half_height).transformed(position, 0.0),
+        Shape::Circle { .. } => unreachable!("Circles are tested against polygons with polygon_circle"),
+    }
+}
+
+fn circle_circle(pa : &glm::Vec2, ra : f32, pb : &glm::Vec2, rb : f32) -> Option<Contact> {
+    let d = pb - pa;
+    let distance = d.norm();
+    if distance >= ra + rb {
+        return None;
+    }
+    let normal = if distance > 0.0 { d / distance } else { glm::vec2(1.0, 0.0) };
+    Some(Contact::single(normal, ra + rb - distance, pa + normal * ra))
+}
+
+/// Normal points from the circle towards the box.
+fn circle_box(pc : &glm::Vec2, r : f32, pb : &glm::Vec2, hw : f32, hh : f32) -> Option<Contact> {
+    let d = pc - pb;
+    let closest = glm::vec2(d.x.max(-hw).min(hw), d.y.max(-hh).min(hh));
+    if closest == d {
+        // The centre is inside the box, so push out along the axis with the least overlap
+        let (ox, oy) = (hw - d.x.abs(), hh - d.y.abs());
+        let normal = if ox < oy {
+            glm::vec2(-d.x.signum(), 0.0)
+        } else {
+            glm::vec2(0.0, -d.y.signum())
+        };
+        return Some(Contact::single(normal, ox.min(oy) + r, pc.clone_owned()));
+    }
+    let offset = d - closest;
+    let distance = offset.norm();
+    if distance >= r {
+        return None;
+    }
+    Some(Contact::single(-offset / distance, r - distance, pb + closest))
+}
+
+fn box_box(pa : &glm::Vec2, wa : f32, ha : f32, pb : &glm::Vec2, wb : f32, hb : f32) -> Option<Contact> {
+    let d = pb - pa;
+    let ox = wa + wb - d.x.abs();
+    let oy = ha + hb - d.y.abs();
+    if ox <= 0.0 || oy <= 0.0 {
+        return None;
+    }
+    // Corners of the overlapping region along the face the boxes get pushed apart through
+    let min = glm::vec2((pa.x - wa).max(pb.x - wb), (pa.y - ha).max(pb.y - hb));
+    let max = glm::vec2((pa.x + wa).min(pb.x + wb), (pa.y + ha).min(pb.y + hb));
+    let middle = (min + max) / 2.0;
+    let (normal, depth, points) = if ox < oy {
+        (glm::vec2(if d.x < 0.0 { -1.0 } else { 1.0 }, 0.0), ox, [glm::vec2(middle.x, min.y), glm::vec2(middle.x, max.y)])
+    } else {
+        (glm::vec2(0.0, if d.y < 0.0 { -1.0 } else { 1.0 }), oy, [glm::vec2(min.x, middle.y), glm::vec2(max.x, middle.y)])
+    };
+    Some(Contact { normal, depth, points, point_count : 2 })
+}
+
+/// Normal points from the polygon towards the circle. Touching counts as a contact of depth zero.
+fn polygon_circle(polygon : &WorldPolygon, c : &glm::Vec2, r : f32) -> Option<Contact> {
+    let (face, separation) = polygon.max_separation(c);
+    if separation > r {
+        return None;
+    }
+    let (v1, v2) = (polygon.vertex(face), polygon.vertex(face + 1));
+    if separation <= 0.0 {
+        let normal = polygon.normal(face);
+        return Some(Contact::single(normal, r - separation, c - normal * r));
+    }
+
+    // Outside the face, so the closest feature is either one of its vertices or the face itself
+    let vertex = if (c - v1).dot(&(v2 - v1)) <= 0.0 {
+        Some(v1)
+    } else if (c - v2).dot(&(v1 - v2)) <= 0.0 {
+        Some(v2)
+    } else {
+        None
+    };
+    match vertex {
+        Some(v) => {
+            let offset = c - v;
+            let distance = offset.norm();
+            if distance > r {
+                return None;
+            }
+            Some(Contact::single(offset / distance, r - distance, v))
+        },
+        None => {
+            let normal = polygon.normal(face);
+            Some(Contact::single(normal, r - separation, c - normal * r))
+        },
+    }
+}
+
+/// The face of a with the largest separation from b, and that separation. Positive means a separating axis.
+fn find_max_separation(a : &WorldPolygon, b : &WorldPolygon) -> (usize, f32) {
+    (0..a.len())
+        .map(|i| {
+            let n = a.normal(i);
+            let v = a.vertex(i);
+            (i, b.vertices().iter().map(|w| n.dot(&(w - v))).fold(f32::INFINITY, f32::min))
+        })
+        .fold((0, f32::NEG_INFINITY), |best, x| if x.1 > best.1 { x } else { best })
+}
+
+/// Separating axis test. When there's no separating axis, the face of least penetration becomes the
+/// reference face, and the most opposing face on the other polygon is clipped against its sides to
+/// find the contact manifold. Touching counts as a contact of depth zero.
+fn polygon_polygon(a : &WorldPolygon, b : &WorldPolygon) -> Option<Contact> {
+    let (face_a, separation_a) = find_max_separation(a, b);
+    if separation_a > 0.0 {
+        return None;
+    }
+    let (face_b, separation_b) = find_max_separation(b, a);
+    if separation_b > 0.0 {
+        return None;
+    }
+
+    // Prefer a's faces when they're about as good, so the choice doesn't flicker between frames
+    let flip = separation_b > separation_a + 1e-4;
+    let (reference, incident, face) = if flip { (b, a, face_b) } else { (a, b, face_a) };
+    let normal = reference.normal(face);
+
+    let incident_face = (0..incident.len())
+        .map(|i| (i, incident.normal(i).dot(&normal)))
+        .fold((0, f32::INFINITY), |best, x| if x.1 < best.1 { x } else { best })
+        .0;
+    let mut points = [incident.vertex(incident_face), incident.vertex(incident_face + 1)];
+
+    let (r1, r2) = (reference.vertex(face), reference.vertex(face + 1));
+    let tangent = (r2 - r1).normalize();
+    if !clip(&mut points, &-tangent, -tangent.dot(&r1)) || !clip(&mut points, &tangent, tangent.dot(&r2)) {
+        return None;
+    }
+
+    let mut manifold = [glm::Vec2::zeros(); 2];
+    let mut count = 0;
+    let mut depth : f32 = 0.0;
+    for p in points.iter() {
+        let separation = normal.dot(&(p - r1));
+        if separation <= 0.0 {
+            manifold[count] = *p;
+            count += 1;
+            depth = depth.max(-separation);
+        }
+    }
+    if count == 0 {
+        return None;
+    }
+    if count == 1 {
+        manifold[1] = manifold[0];
+    }
+    Some(Contact {
+        normal : if flip { -normal } else { normal },
+        depth,
+        points : manifold,
+        point_count : count,
+    })
+}
+
+/// Sutherland-Hodgman clipping of a segment against the half plane n·p <= offset.
+/// False if the whole segment is outside.
+fn clip(points : &mut [glm::Vec2; 2], n : &glm::Vec2, offset : f32) -> bool {
+    let d0 = n.dot(&points[0]) - offset;
+    let d1 = n.dot(&points[1]) - offset;
+    if d0 > 0.0 && d1 > 0.0 {
+        return false;
+    }
+    let crossing = points[0] + (points[1] - points[0]) * (d0 / (d0 - d1));
+    if d0 > 0.0 {
+        points[0] = crossing;
+    } else if d1 > 0.0 {
+        points[1] = crossing;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square() -> Shape {
+        Shape::Polygon(Polygon::rectangle(0.5, 0.5))
+    }
+
+    fn at(x : f32, y : f32) -> Position {
+        Position { x, y }
+    }
+
+    #[test]
+    fn overlapping_squares_push_apart_along_the_shallowest_axis() {
+        let c = contact(&at(0.0, 0.0), 0.0, &square(), &at(0.8, 0.1), 0.0, &square()).unwrap();
+        assert!((c.normal - glm::vec2(1.0, 0.0)).norm() < 1e-6, "normal {:?}", c.normal);
+        assert!((c.depth - 0.2).abs() < 1e-5, "depth {}", c.depth);
+        assert_eq!(c.points().len(), 2);
+
+        let c = contact(&at(0.8, 0.1), 0.0, &square(), &at(0.0, 0.0), 0.0, &square()).unwrap();
+        assert!((c.normal - glm::vec2(-1.0, 0.0)).norm() < 1e-6, "normal {:?}", c.normal);
+        assert!((c.depth - 0.2).abs() < 1e-5);
+    }
+
+    #[test]
+    fn corner_into_a_face_touches_at_one_point() {
+        let corner = 0.5 * std::f32::consts::SQRT_2;
+        let c = contact(&at(0.0, 0.0), 0.0, &square(), &at(0.0, 1.1), std::f32::consts::FRAC_PI_4, &square()).unwrap();
+        assert!((c.normal - glm::vec2(0.0, 1.0)).norm() < 1e-5, "normal {:?}", c.normal);
+        assert!((c.depth - (0.5 - (1.1 - corner))).abs() < 1e-5, "depth {}", c.depth);
+        assert_eq!(c.points().len(), 1);
+        assert!((c.points()[0] - glm::vec2(0.0, 1.1 - corner)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn edges_lying_on_each_other_touch_at_depth_zero() {
+        let c = contact(&at(0.0, 0.0), 0.0, &square(), &at(1.0, 0.25), 0.0, &square()).unwrap();
+        assert!((c.normal - glm::vec2(1.0, 0.0)).norm() < 1e-6, "normal {:?}", c.normal);
+        assert_eq!(c.depth, 0.0);
+        assert_eq!(c.points().len(), 2);
+    }
+
+    #[test]
+    fn separated_shapes_have_no_contact() {
+        assert!(contact(&at(0.0, 0.0), 0.0, &square(), &at(1.1, 0.0), 0.0, &square()).is_none());
+        // Their bounding boxes overlap, only the diagonal axis separates them
+        assert!(contact(&at(0.0, 0.0), 0.0, &square(), &at(0.95, 0.95), std::f32::consts::FRAC_PI_4, &square()).is_none());
+    }
+
+    fn circle(radius : f32) -> Shape {
+        Shape::Circle { radius }
+    }
+
+    #[test]
+    fn circles_against_a_face_push_out_along_its_normal() {
+        let c = contact(&at(0.0, 0.0), 0.0, &square(), &at(0.6, 0.0), 0.0, &circle(0.2)).unwrap();
+        assert!((c.normal - glm::vec2(1.0, 0.0)).norm() < 1e-6, "normal {:?}", c.normal);
+        assert!((c.depth - 0.1).abs() < 1e-5, "depth {}", c.depth);
+        assert!((c.points()[0] - glm::vec2(0.4, 0.0)).norm() < 1e-5);
+
+        // Centre inside the polygon
+        let c = contact(&at(0.0, 0.0), 0.0, &square(), &at(0.4, 0.1), 0.0, &circle(0.2)).unwrap();
+        assert!((c.normal - glm::vec2(1.0, 0.0)).norm() < 1e-6, "normal {:?}", c.normal);
+        assert!((c.depth - 0.3).abs() < 1e-5, "depth {}", c.depth);
+
+        // The other way around, the normal still points from the first shape to the second
+        let c = contact(&at(0.6, 0.0), 0.0, &circle(0.2), &at(0.0, 0.0), 0.0, &square()).unwrap();
+        assert!((c.normal - glm::vec2(-1.0, 0.0)).norm() < 1e-6, "normal {:?}", c.normal);
+        assert!((c.depth - 0.1).abs() < 1e-5, "depth {}", c.depth);
+    }
+
+    #[test]
+    fn circles_past_a_corner_push_out_from_the_vertex() {
+        let c = contact(&at(0.0, 0.0), 0.0, &square(), &at(0.6, 0.6), 0.0, &circle(0.2)).unwrap();
+        let diagonal = glm::vec2(1.0, 1.0).normalize();
+        assert!((c.normal - diagonal).norm() < 1e-5, "normal {:?}", c.normal);
+        assert!((c.depth - (0.2 - 0.1 * std::f32::consts::SQRT_2)).abs() < 1e-5, "depth {}", c.depth);
+        assert!((c.points()[0] - glm::vec2(0.5, 0.5)).norm() < 1e-5);
+    }
+
+    #[test]
+    fn circles_touching_a_face_have_depth_zero() {
+        let c = contact(&at(0.0, 0.0), 0.0, &square(), &at(0.75, 0.0), 0.0, &circle(0.25)).unwrap();
+        assert!((c.normal - glm::vec2(1.0, 0.0)).norm() < 1e-6, "normal {:?}", c.normal);
+        assert_eq!(c.depth, 0.0);
+    }
+
+    #[test]
+    fn separated_circles_have_no_contact() {
+        assert!(contact(&at(0.0, 0.0), 0.0, &square(), &at(0.8, 0.0), 0.0, &circle(0.2)).is_none());
+        // Within reach of both faces next to the corner, but not of the corner itself
+        assert!(contact(&at(0.0, 0.0), 0.0, &square(), &at(0.7, 0.7), 0.0, &circle(0.2)).is_none());
+    }
+}
@@ -0,0 +1,103 @@
+
+use super::{EntityId, Game};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConstraintKind {
+    /// Keeps the entities rest_length apart, like a rod or a chain link
+    Distance,
+    /// Pulls the entities towards rest_length with a damped spring force
+    Spring,
+    /// Holds the entities together, as a distance constraint with no length.
+    /// Pinning to an entity without a velocity pins to a fixed point.
+    Pin,
+}
+
+/// Link between two entities, solved every update after integration.
+#[derive(Debug, Clone)]
+pub struct Constraint {
+    pub a : EntityId,
+    pub b : EntityId,
+    pub kind : ConstraintKind,
+    pub rest_length : f32,
+    /// For springs, the force per unit of stretch. Otherwise the fraction of the length error
+    /// corrected each solver iteration, where 1 is rigid.
+    pub stiffness : f32,
+    /// For springs, the force per unit of speed along the link. Otherwise the fraction of the speed
+    /// along the link removed each update, where 1 keeps the link from stretching at all.
+    pub damping : f32,
+}
+
+impl Constraint {
+    pub fn distance(a : EntityId, b : EntityId, rest_length : f32) -> Self {
+        Constraint { a, b, kind : ConstraintKind::Distance, rest_length, stiffness : 1.0, damping : 1.0 }
+    }
+
+    pub fn spring(a : EntityId, b : EntityId, rest_length : f32, stiffness : f32, damping : f32) -> Self {
+        Constraint { a, b, kind : ConstraintKind::Spring, rest_length, stiffness, damping }
+    }
+
+    pub fn pin(a : EntityId, b : EntityId) -> Self {
+        Constraint { a, b, kind : ConstraintKind::Pin, rest_length : 0.0, stiffness : 1.0, damping : 1.0 }
+    }
+}
+
+impl Game {
+    /// Springs get one velocity kick per update. Distance and pin constraints are solved by moving
+    /// the entities towards their rest length, a few times over so chains settle, and then losing
+    /// the speed that would stretch them again.
+    pub(super) fn solve_constraints(&mut self) {
+        let dt = self.physics.time_step;
+        for c in self.constraints.iter().filter(|c| c.kind == ConstraintKind::Spring) {
+            let (wa, wb) = (self.inverse_mass(c.a), self.inverse_mass(c.b));
+            if wa + wb == 0.0 {
+                continue;
+            }
+            let (n, length) = self.link(c.a, c.b);
+            let (va, vb) = (&self.velocities[c.a as usize], &self.velocities[c.b as usize]);
+            let speed = glm::vec2(vb.x - va.x, vb.y - va.y).dot(&n);
+            let impulse = n * (c.stiffness * (length - c.rest_length) + c.damping * speed) * dt;
+            self.velocities[c.a as usize].x += impulse.x * wa;
+            self.velocities[c.a as usize].y += impulse.y * wa;
+            self.velocities[c.b as usize].x -= impulse.x * wb;
+            self.velocities[c.b as usize].y -= impulse.y * wb;
+        }
+
+        for _ in 0..self.physics.constraint_iterations {
+            for c in self.constraints.iter().filter(|c| c.kind != ConstraintKind::Spring) {
+                let (wa, wb) = (self.inverse_mass(c.a), self.inverse_mass(c.b));
+                if wa + wb == 0.0 {
+                    continue;
+                }
+                let (n, length) = self.link(c.a, c.b);
+                let correction = n * (length - c.rest_length) * c.stiffness / (wa + wb);
+                self.positions[c.a as usize].x += correction.x * wa;
+                self.positions[c.a as usize].y += correction.y * wa;
+                self.positions[c.b as usize].x -= correction.x * wb;
+                self.positions[c.b as usize].y -= correction.y * wb;
+            }
+        }
+
+        for c in self.constraints.iter().filter(|c| c.kind != ConstraintKind::Spring) {
+            let (wa, wb) = (self.inverse_mass(c.a), self.inverse_mass(c.b));
+            if wa + wb == 0.0 {
+                continue;
+            }
+            let (n, _) = self.link(c.a, c.b);
+            let (va, vb) = (&self.velocities[c.a as usize], &self.velocities[c.b as usize]);
+            let speed = glm::vec2(vb.x - va.x, vb.y - va.y).dot(&n);
+            let impulse = n * speed * c.damping / (wa + wb);
+            self.velocities[c.a as usize].x += impulse.x * wa;
+            self.velocities[c.a as usize].y += impulse.y * wa;
+            self.velocities[c.b as usize].x -= impulse.x * wb;
+            self.velocities[c.b as usize].y -= impulse.y * wb;
+        }
+    }
+
+    /// Unit direction from a to b, and the distance between them.
+    fn link(&self, a : EntityId, b : EntityId) -> (glm::Vec2, f32) {
+        let (pa, pb) = (&self.positions[a as usize], &self.positions[b as usize]);
+        let d = glm::vec2(pb.x - pa.x, pb.y - pa.y);
+        let length = d.norm();
+        if length > 0.0 { (d / length, length) } else { (glm::vec2(1.0, 0.0), 0.0) }
+    }
+}
@@ -0,0 +1,95 @@
+
+use super::{CompFlag, EntityId, Game, Velocity};
+
+/// Boids. Every flocking entity steers away from neighbours that crowd it, towards their average
+/// heading, and towards their centre. Weights are how quickly each rule changes the velocity, per second.
+#[derive(Debug, Clone)]
+pub struct Flocking {
+    pub separation : f32,
+    pub alignment : f32,
+    pub cohesion : f32,
+    /// How far a flocking entity sees its neighbours. Neighbours are found quickest with grid cells about this size.
+    pub view_radius : f32,
+    /// Flocks keep cruising instead of averaging their headings out to standing still
+    pub min_speed : f32,
+    pub max_speed : f32,
+}
+
+impl Default for Flocking {
+    fn default() -> Self {
+        Self {
+            separation : 1.5,
+            alignment : 1.0,
+            cohesion : 1.0,
+            view_radius : 0.1,
+            min_speed : 0.2,
+            max_speed : 0.5,
+        }
+    }
+}
+
+impl Game {
+    /// Steers every flocking entity by its neighbours. All velocities are read before any is
+    /// changed, so the order entities are visited in doesn't matter.
+    pub(super) fn flock(&mut self) {
+        let flocking = match &self.flocking {
+            Some(f) => f,
+            None => return,
+        };
+        let dt = self.physics.time_step;
+        let is_flocking = |x : CompFlag| x.contains(CompFlag::Pos | CompFlag::Vel | CompFlag::Flk) && !x.intersects(CompFlag::Sta | CompFlag::Slp);
+
+        let steered : Vec<(EntityId, Velocity)> = self.entities.iter()
+            .filter(|x| is_flocking(x.components))
+            .map(|x| {
+                let id = x.id as usize;
+                let (pos, vel) = (&self.positions[id], &self.velocities[id]);
+                let p = glm::vec2(pos.x, pos.y);
+                let mut v = glm::vec2(vel.x, vel.y);
+
+                let mut separation = glm::Vec2::zeros();
+                let mut heading = glm::Vec2::zeros();
+                let mut centre = glm::Vec2::zeros();
+                let mut count = 0;
+                for other in self.spacially_sorted.find_within(pos, flocking.view_radius) {
+                    if other == x.id || !is_flocking(self.entities[other as usize].components) {
+                        continue;
+                    }
+                    let (op, ov) = (&self.positions[other as usize], &self.velocities[other as usize]);
+                    let d = p - glm::vec2(op.x, op.y);
+                    let distance = d.norm();
+                    if distance >= flocking.view_radius {
+                        continue;
+                    }
+                    // Pushes harder the closer the neighbour is
+                    if distance > 0.0 {
+                        separation += d / distance * (1.0 - distance / flocking.view_radius);
+                    }
+                    heading += glm::vec2(ov.x, ov.y);
+                    centre += glm::vec2(op.x, op.y);
+                    count += 1;
+                }
+
+                if count > 0 {
+                    let n = count as f32;
+                    // Every rule is scaled to a velocity, so the weights are comparable
+                    let steer = separation * flocking.max_speed * flocking.separation
+                        + (heading / n - v) * flocking.alignment
+                        + (centre / n - p) / flocking.view_radius * flocking.max_speed * flocking.cohesion;
+                    v += steer * dt;
+                }
+                let speed = v.norm();
+                if speed > flocking.max_speed {
+                    v *= flocking.max_speed / speed;
+                } else if speed < flocking.min_speed && speed > 0.0 {
+                    v *= flocking.min_speed / speed;
+                }
+                (x.id, Velocity { x : v.x, y : v.y })
+            })
+            .collect();
+
+        for (id, vel) in steered {
+            self.velocities[id as usize] = vel;
+        }
+    }
+}
@@ -0,0 +1,135 @@
+
+use std::f32::consts::PI;
+
+use super::{CompFlag, EntityId, Game};
+
+/// Smoothed particle hydrodynamics. Every fluid particle's density is smoothed over its neighbours
+/// within the kernel radius, turned into a pressure, and the particles are pushed from high to low
+/// pressure and dragged along with each other by viscosity. Particles weigh their mass.
+#[derive(Debug, Clone)]
+pub struct Fluid {
+    /// How far each particle reaches. Neighbours are found quickest with grid cells this size.
+    pub kernel_radius : f32,
+    /// Density where the pressure is zero. With unit masses, that's one over the square of the
+    /// spacing the particles settle at.
+    pub rest_density : f32,
+    /// Pressure per unit of density above the rest density
+    pub stiffness : f32,
+    pub viscosity : f32,
+}
+
+impl Default for Fluid {
+    fn default() -> Self {
+        Self {
+            kernel_radius : 0.05,
+            rest_density : 1600.0,
+            stiffness : 1.0,
+            viscosity : 1.0,
+        }
+    }
+}
+
+/// 2D kernels from Müller et al., Particle-Based Fluid Simulation for Interactive Applications.
+impl Fluid {
+    /// Poly6, smooths the density.
+    fn density_kernel(&self, r : f32) -> f32 {
+        let h = self.kernel_radius;
+        4.0 / (PI * h.powi(8)) * (h * h - r * r).powi(3)
+    }
+
+    /// Gradient of the spiky kernel along the direction away from the neighbour. Unlike poly6's,
+    /// it doesn't flatten out when particles get close.
+    fn pressure_kernel(&self, r : f32) -> f32 {
+        let h = self.kernel_radius;
+        -30.0 / (PI * h.powi(5)) * (h - r).powi(2)
+    }
+
+    /// Laplacian of the viscosity kernel.
+    fn viscosity_kernel(&self, r : f32) -> f32 {
+        let h = self.kernel_radius;
+        40.0 / (PI * h.powi(5)) * (h - r)
+    }
+
+    /// Clamped at zero, so a free surface doesn't pull particles into clumps.
+    fn pressure(&self, density : f32) -> f32 {
+        (self.stiffness * (density - self.rest_density)).max(0.0)
+    }
+}
+
+/// Unit direction from b to a. Particles on top of each other, like ones the world bounds pressed
+/// into the same corner, get one picked from their ids, opposite for the other of the pair, so
+/// pressure can tell them apart again.
+fn separating_direction(a : EntityId, b : EntityId, d : &glm::Vec2, r : f32) -> glm::Vec2 {
+    if r > 0.0 {
+        return d / r;
+    }
+    let angle = a.min(b) as f32 * 2.399_963;
+    let direction = glm::vec2(angle.cos(), angle.sin());
+    if a < b { direction } else { -direction }
+}
+
+impl Game {
+    /// The density pass over every fluid particle, then the pressure and viscosity passes over the
+    /// moving ones. New velocities go to the collision buffer and are swapped in at the end, so
+    /// every particle sees its neighbours as they were at the start of the update.
+    pub(super) fn simulate_fluid(&mut self) {
+        let fluid = match &self.fluid {
+            Some(f) => f,
+            None => return,
+        };
+        let h = fluid.kernel_radius;
+        let is_fluid = |x : CompFlag| x.contains(CompFlag::Pos | CompFlag::Fld);
+        let (entities, masses, positions) = (&self.entities, &self.masses, &self.positions);
+        let mass = |id : EntityId| if entities[id as usize].components.contains(CompFlag::Mas) { masses[id as usize].value } else { 1.0 };
+        let offset = |a : EntityId, b : EntityId| {
+            let (pa, pb) = (&positions[a as usize], &positions[b as usize]);
+            glm::vec2(pa.x - pb.x, pa.y - pb.y)
+        };
+
+        // Neighbours within the kernel radius, including the particle itself
+        let particles : Vec<(EntityId, Vec<EntityId>)> = self.entities.iter()
+            .filter(|x| is_fluid(x.components))
+            .map(|x| {
+                let nearby = self.spacially_sorted.find_within(&self.positions[x.id as usize], h)
+                    .into_iter()
+                    .filter(|other| is_fluid(self.entities[*other as usize].components) && offset(x.id, *other).norm() < h)
+                    .collect();
+                (x.id, nearby)
+            })
+            .collect();
+
+        for (id, nearby) in particles.iter() {
+            self.densities[*id as usize] = nearby.iter()
+                .map(|other| mass(*other) * fluid.density_kernel(offset(*id, *other).norm()))
+                .sum();
+        }
+
+        self.collision_buffer_vel.clone_from_slice(&self.velocities);
+        let dt = self.physics.time_step;
+        for (id, nearby) in particles.iter() {
+            let i = *id as usize;
+            if !self.entities[i].components.contains(CompFlag::Vel) || self.entities[i].components.intersects(CompFlag::Sta | CompFlag::Slp) {
+                continue;
+            }
+            let (density, pressure) = (self.densities[i], fluid.pressure(self.densities[i]));
+            // Not in the grid yet, so it hasn't found its neighbours or even itself
+            if density <= 0.0 {
+                continue;
+            }
+            let mut acc = glm::Vec2::zeros();
+            for &other in nearby.iter().filter(|other| **other != *id) {
+                let j = other as usize;
+                let d = offset(*id, other);
+                let r = d.norm();
+                let m = mass(other) / self.densities[j];
+                acc -= separating_direction(*id, other, &d, r) * m * (pressure + fluid.pressure(self.densities[j])) / 2.0 * fluid.pressure_kernel(r);
+                let relative = glm::vec2(self.velocities[j].x - self.velocities[i].x, self.velocities[j].y - self.velocities[i].y);
+                acc += relative * m * fluid.viscosity * fluid.viscosity_kernel(r);
+            }
+            acc /= density;
+            self.collision_buffer_vel[i].x += acc.x * dt;
+            self.collision_buffer_vel[i].y += acc.y * dt;
+        }
+        std::mem::swap(&mut self.velocities, &mut self.collision_buffer_vel);
+    }
+}
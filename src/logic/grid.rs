@@ -1,9 +1,15 @@
-use std::{cell::{Ref, RefCell}, collections::HashMap};
+
+use std::{cell::{Ref, RefCell}, collections::BTreeMap};
 use super::{EntityId, Position};
+use super::snapshot::{Reader, Save, Writer};
+
+/// Grid cell coordinates. Signed, so positions left of or below the origin get cells of their own.
+pub type Cell = (i32, i32);
 
 pub struct EntityGrid {
-    sorted : HashMap<(usize, usize), RefCell<Vec<EntityId>>>,
-    locations : Vec<(usize, usize)>,
+    /// Ordered by cell, so nothing going over the cells can depend on hashing
+    sorted : BTreeMap<Cell, RefCell<Vec<EntityId>>>,
+    locations : Vec<Option<Cell>>,
     scale_factor : f32,
 }
 
@@ -11,61 +17,127 @@ impl EntityGrid {
     pub fn new(scale_factor : f32) -> Self {
         Self {
             scale_factor,
-            sorted : HashMap::new(),
+            sorted : BTreeMap::new(),
             locations : Vec::new(),
         }
     }
 
-    fn get_location(&self, pos : &Position) -> (usize, usize) {
-        ((pos.x/self.scale_factor) as usize, (pos.y/self.scale_factor) as usize)
+    /// The side length of a single cell.
+    pub fn cell_size(&self) -> f32 {
+        self.scale_factor
     }
 
-    fn fill_loc(&mut self, id : EntityId, loc : &(usize,usize)) {
+    pub fn get_location(&self, pos : &Position) -> Cell {
+        ((pos.x/self.scale_factor).floor() as i32, (pos.y/self.scale_factor).floor() as i32)
+    }
+
+    fn fill_loc(&mut self, id : EntityId, loc : &Cell) {
         if let None = self.sorted.get(loc) {
             self.sorted.insert(*loc, RefCell::new(vec![id]));
         } else {
             self.sorted.get(loc).unwrap().borrow_mut().push(id);
         }
         if self.locations.len() <= id as usize {
-            self.locations.push(*loc);
-        } else {
-            self.locations[id as usize] = *loc;
+            self.locations.resize(id as usize + 1, None);
+        }
+        self.locations[id as usize] = Some(*loc);
+    }
+
+    fn clear_loc(&mut self, id : EntityId, loc : &Cell) {
+        let l = self.sorted.get(loc).unwrap();
+        let mut l = l.borrow_mut();
+        if let Some(index) = l.iter().position(|f| *f == id) {
+            l.swap_remove(index);
         }
     }
 
     /// The iterator given has to contain all positions.
     /// TODO: Deal with unpositioned entities
     pub fn sort<'a>(&mut self, positions : impl Iterator<Item = &'a Position>) {
-        
-        for (i, pos) in positions.enumerate() {
-            let loc = self.get_location(pos);
 
-            self.fill_loc(i as EntityId, &loc);
+        for (i, pos) in positions.enumerate() {
+            self.sort_single(i as EntityId, pos);
         }
     }
 
+    /// Moves the entity to the cell containing pos, inserting it if the grid hasn't seen it before.
     pub fn sort_single(&mut self, id : EntityId, pos : &Position) {
         let loc = self.get_location(pos);
-        if let Some(k) = self.locations.get(id as usize) {
-            if *k != loc {
-                {
-                    let l = self.sorted.get(k).unwrap();
-                    let mut l = l.borrow_mut();
-                    let index = *(l.iter().find(|f| **f == id).unwrap());
-                    l.remove(index as usize);
-                }
-
+        match self.locations.get(id as usize).copied().flatten() {
+            Some(k) if k == loc => (),
+            Some(k) => {
+                self.clear_loc(id, &k);
                 self.fill_loc(id, &loc);
-            }
+            },
+            None => self.fill_loc(id, &loc),
+        }
+    }
+
+    /// Takes the entity out of the grid, until it's sorted in again.
+    pub fn remove(&mut self, id : EntityId) {
+        if let Some(loc) = self.locations.get(id as usize).copied().flatten() {
+            self.clear_loc(id, &loc);
+            self.locations[id as usize] = None;
         }
     }
 
     pub fn find_nearby(&self, pos :  &Position) -> Option<Ref<Vec<u16>>> {
         let loc = self.get_location(pos);
-        let result = self.sorted.get(&loc);
-        match result {
-            None => None,
-            Some(rc) => Some(rc.borrow())
+        self.find_in_cell(&loc)
+    }
+
+    /// Everything sorted into the cells overlapping the square around pos that reaches radius out to each side.
+    /// Candidates only, some of them can be further than radius away.
+    pub fn find_within(&self, pos : &Position, radius : f32) -> Vec<EntityId> {
+        let min = self.get_location(&Position { x : pos.x - radius, y : pos.y - radius });
+        let max = self.get_location(&Position { x : pos.x + radius, y : pos.y + radius });
+        let mut found = Vec::new();
+        for cx in min.0..=max.0 {
+            for cy in min.1..=max.1 {
+                if let Some(nearby) = self.find_in_cell(&(cx, cy)) {
+                    found.extend_from_slice(&nearby);
+                }
+            }
         }
+        found
+    }
+
+    /// How many ids the grid keeps a location for, sorted in or not.
+    pub(super) fn tracked(&self) -> usize {
+        self.locations.len()
+    }
+
+    /// Every entity sorted into a cell.
+    pub(super) fn sorted_ids(&self) -> Vec<EntityId> {
+        self.sorted.values().flat_map(|ids| ids.borrow().clone()).collect()
+    }
+
+    /// The entities sorted into the given cell, if the cell has ever been filled.
+    pub fn find_in_cell(&self, loc : &Cell) -> Option<Ref<'_, Vec<EntityId>>> {
+        self.sorted.get(loc).map(|rc| rc.borrow())
     }
-}
\ No newline at end of file
+}
+
+/// Cells are saved with their entities in order, since that's the order they're found in.
+impl Save for EntityGrid {
+    fn save(&self, w : &mut Writer) {
+        self.scale_factor.save(w);
+        self.sorted.len().save(w);
+        for (cell, ids) in &self.sorted {
+            cell.save(w);
+            ids.borrow().save(w);
+        }
+        self.locations.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let mut grid = EntityGrid::new(Save::load(r)?);
+        let cells : Vec<(Cell, Vec<EntityId>)> = Save::load(r)?;
+        grid.sorted = cells.into_iter().map(|(cell, ids)| (cell, RefCell::new(ids))).collect();
+        grid.locations = Save::load(r)?;
+        if let Some(cell) = grid.locations.iter().flatten().find(|cell| !grid.sorted.contains_key(cell)) {
+            return Err(format!("Snapshot has an entity in grid cell {:?}, which it doesn't have", cell));
+        }
+        Ok(grid)
+    }
+}
@@ -0,0 +1,145 @@
+
+use super::{Position, Velocity};
+
+/// Force accumulator. Systems add to it during a tick, integration turns it into acceleration and clears it.
+#[derive(Default, Debug, Clone)]
+pub struct Force {
+    pub x : f32,
+    pub y : f32,
+    pub torque : f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Integrator {
+    /// Updates velocity first, then moves by the new velocity
+    SemiImplicitEuler,
+    /// Kicks the velocity by half the acceleration, moves by that velocity, then kicks it by half
+    /// the acceleration at the new position. Exact for constant acceleration, like projectiles
+    /// under gravity, and keeps orbits from gaining energy.
+    VelocityVerlet,
+}
+
+impl Integrator {
+    /// Whether finish needs the acceleration sampled again where begin moved the entity
+    pub fn resamples(&self) -> bool {
+        matches!(self, Integrator::VelocityVerlet)
+    }
+
+    /// First part of a step, from the acceleration at the entity's current position. Returns where
+    /// the entity ends up, and its velocity as far as finish has to take it.
+    pub fn begin(&self, pos : &Position, vel : &Velocity, acc : &glm::Vec2, dt : f32) -> (Position, Velocity) {
+        let kick = match self {
+            Integrator::SemiImplicitEuler => dt,
+            Integrator::VelocityVerlet => 0.5 * dt,
+        };
+        let vel = Velocity { x : vel.x + acc.x * kick, y : vel.y + acc.y * kick };
+        (Position { x : pos.x + vel.x * dt, y : pos.y + vel.y * dt }, vel)
+    }
+
+    /// Rest of a step, from the acceleration at the position begin moved the entity to.
+    pub fn finish(&self, vel : &Velocity, acc : &glm::Vec2, dt : f32) -> Velocity {
+        match self {
+            Integrator::SemiImplicitEuler => vel.clone(),
+            Integrator::VelocityVerlet => Velocity { x : vel.x + acc.x * 0.5 * dt, y : vel.y + acc.y * 0.5 * dt },
+        }
+    }
+
+    /// Advances one entity by dt, with its acceleration at any position given by acceleration.
+    pub fn step(&self, pos : &Position, vel : &Velocity, dt : f32, acceleration : impl Fn(&Position) -> glm::Vec2) -> (Position, Velocity) {
+        let (pos, vel) = self.begin(pos, vel, &acceleration(pos), dt);
+        let vel = self.finish(&vel, &acceleration(&pos), dt);
+        (pos, vel)
+    }
+}
+
+pub struct PhysicsConfig {
+    /// Acceleration applied to everything with a velocity
+    pub gravity : glm::Vec2,
+    /// Fraction of velocity lost per second, applied as v / (1 + damping * dt)
+    pub linear_damping : f32,
+    /// Same as linear_damping, for angular velocity
+    pub angular_damping : f32,
+    pub integrator : Integrator,
+    /// Simulated seconds per update
+    pub time_step : f32,
+    /// How many times distance and pin constraints are relaxed per update
+    pub constraint_iterations : usize,
+    /// Average speed, linear or angular, below which an entity counts as resting
+    pub sleep_velocity : f32,
+    /// Seconds an entity has to rest before it's put to sleep
+    pub time_to_sleep : f32,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            gravity : glm::vec2(0.0, 0.0),
+            linear_damping : 0.0,
+            angular_damping : 0.0,
+            integrator : Integrator::SemiImplicitEuler,
+            time_step : 1.0 / 60.0,
+            constraint_iterations : 8,
+            sleep_velocity : 0.01,
+            time_to_sleep : 0.5,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GRAVITY : f32 = -9.81;
+
+    /// Where a projectile thrown from the origin at (3, 4) is after steps of dt, and where it
+    /// should be.
+    fn throw(integrator : Integrator, dt : f32, steps : usize) -> (Position, Velocity, f32) {
+        let (mut pos, mut vel) = (Position::default(), Velocity { x : 3.0, y : 4.0 });
+        for _ in 0..steps {
+            let (p, v) = integrator.step(&pos, &vel, dt, |_| glm::vec2(0.0, GRAVITY));
+            pos = p;
+            vel = v;
+        }
+        (pos, vel, dt * steps as f32)
+    }
+
+    #[test]
+    fn verlet_projectile_matches_the_analytic_arc() {
+        let (pos, vel, t) = throw(Integrator::VelocityVerlet, 1.0 / 60.0, 60);
+        assert!((pos.x - 3.0 * t).abs() < 1e-4);
+        assert!((pos.y - (4.0 * t + 0.5 * GRAVITY * t * t)).abs() < 1e-4, "y is {}", pos.y);
+        assert!((vel.y - (4.0 + GRAVITY * t)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn euler_projectile_drops_half_a_step_of_gravity_per_second() {
+        let dt = 1.0 / 60.0;
+        let (pos, vel, t) = throw(Integrator::SemiImplicitEuler, dt, 60);
+        assert!((pos.x - 3.0 * t).abs() < 1e-4);
+        // Every step moves by the velocity at its end, which runs ahead of the arc by g dt / 2
+        let analytic = 4.0 * t + 0.5 * GRAVITY * t * t;
+        assert!((pos.y - (analytic + 0.5 * GRAVITY * dt * t)).abs() < 1e-3, "y is {}", pos.y);
+        assert!((vel.y - (4.0 + GRAVITY * t)).abs() < 1e-4);
+    }
+
+    /// Error of a unit spring started at x = 1 after one period, which should bring it back to 1.
+    fn spring_error(integrator : Integrator, steps : usize) -> f32 {
+        let dt = std::f32::consts::TAU / steps as f32;
+        let (mut pos, mut vel) = (Position { x : 1.0, y : 0.0 }, Velocity::default());
+        for _ in 0..steps {
+            let (p, v) = integrator.step(&pos, &vel, dt, |p| glm::vec2(-p.x, 0.0));
+            pos = p;
+            vel = v;
+        }
+        ((pos.x - 1.0).powi(2) + vel.x.powi(2)).sqrt()
+    }
+
+    #[test]
+    fn verlet_is_second_order_when_the_acceleration_depends_on_position() {
+        let coarse = spring_error(Integrator::VelocityVerlet, 100);
+        let fine = spring_error(Integrator::VelocityVerlet, 200);
+        // Halving the step should quarter the error
+        assert!(fine < coarse / 3.0, "errors {} and {}", coarse, fine);
+        assert!(coarse < 1e-2);
+    }
+}
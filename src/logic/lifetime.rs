@@ -0,0 +1,44 @@
+
+use super::{CompFlag, EntityId, Game, PrefabId};
+
+/// Seconds an entity has left before it's despawned.
+#[derive(Debug, Clone, Default)]
+pub struct Lifetime {
+    pub remaining : f32,
+    /// Spawned where the entity was when it expired, like an explosion after a rocket
+    pub on_expire : Option<PrefabId>,
+}
+
+impl Lifetime {
+    pub fn new(seconds : f32) -> Self {
+        Self { remaining : seconds, on_expire : None }
+    }
+
+    pub fn then(seconds : f32, prefab : PrefabId) -> Self {
+        Self { remaining : seconds, on_expire : Some(prefab) }
+    }
+}
+
+impl Game {
+    /// Counts every lifetime down, despawns the entities whose time is up and spawns their replacements.
+    pub(super) fn expire_lifetimes(&mut self) {
+        let dt = self.physics.time_step;
+        let mut expired : Vec<EntityId> = Vec::new();
+        for entity in self.entities.iter().filter(|x| x.components.contains(CompFlag::Ttl)) {
+            let lifetime = &mut self.lifetimes[entity.id as usize];
+            lifetime.remaining -= dt;
+            if lifetime.remaining <= 0.0 {
+                expired.push(entity.id);
+            }
+        }
+        for id in expired {
+            let i = id as usize;
+            let replacement = self.lifetimes[i].on_expire;
+            let position = self.positions[i].clone();
+            self.despawn(id);
+            if let Some(prefab) = replacement {
+                self.spawn_prefab(prefab, &position);
+            }
+        }
+    }
+}
@@ -1,12 +1,63 @@
 mod grid;
+mod raycast;
+mod collision;
+mod broadphase;
+mod ccd;
+mod sensor;
+mod integrator;
+mod polygon;
+mod constraint;
+mod bounds;
+mod sleep;
+mod attraction;
+mod flocking;
+mod fluid;
+mod steering;
+mod tilemap;
+mod navigation;
+mod particles;
+mod prefab;
+mod lifetime;
+mod tween;
+mod state_machine;
+mod spawner;
+mod time;
+mod random;
+mod checksum;
+mod snapshot;
 
 
-use std::{cell::RefCell, sync::mpsc::{SyncSender}};
+use std::{cell::RefCell, sync::mpsc::{SyncSender, TrySendError}};
 
 use bitflags::bitflags;
 
 use self::grid::EntityGrid;
-type EntityId = u16;
+pub use self::broadphase::{Broadphase, SweepAndPrune, SweepAxis};
+use self::raycast::QueryFilter;
+pub use self::sensor::{Sensor, SensorEvent};
+pub use self::integrator::{Force, Integrator, PhysicsConfig};
+pub use self::polygon::Polygon;
+pub use self::constraint::Constraint;
+pub use self::bounds::{BoundaryPolicy, WorldBounds};
+use self::sleep::Rest;
+pub use self::attraction::Attraction;
+pub use self::flocking::Flocking;
+pub use self::fluid::Fluid;
+pub use self::steering::{Behaviour, Steering, Target};
+pub use self::tilemap::{Tilemap, TileChunk, Tileset, EMPTY_TILE};
+pub use self::navigation::{NavGrid, Navigation};
+pub use self::particles::{ParticleEmitter, ParticlePool, ParticleSprite};
+pub use self::prefab::{Prefab, PrefabId};
+pub use self::lifetime::Lifetime;
+pub use self::tween::{Ease, Property, Repeat, Tween, Tweening};
+use self::tween::TweenEvent;
+pub use self::state_machine::{Machine, StateMachine};
+pub use self::spawner::{Schedule, SpawnArea, Spawner, Wave};
+pub use self::time::TimeControl;
+pub use self::random::{Randomness, Stream};
+pub use self::checksum::ChecksumLog;
+use self::checksum::Checksum;
+pub type EntityId = u16;
 
 
 bitflags! {
@@ -14,6 +65,41 @@ bitflags! {
         const Pos = 0b1;
         const Vel = 0b10;
         const Ass = 0b100;
+        const Col = 0b1000;
+        /// Swept against colliders every tick, so fast movers don't tunnel through them
+        const Ccd = 0b10000;
+        /// Reports what overlaps its collider instead of colliding with it
+        const Sen = 0b100000;
+        /// Has a force accumulator that's applied and cleared every update
+        const Frc = 0b1000000;
+        const Rot = 0b10000000;
+        /// Spins. Needs Rot to have something to turn.
+        const Ang = 0b100000000;
+        /// Has its own boundary policy instead of the world bounds' default
+        const Bnd = 0b1000000000;
+        /// Never moves on its own. Isn't integrated, and collides from the static partition.
+        const Sta = 0b10000000000;
+        /// Came to rest, so it's skipped by integration until something wakes it
+        const Slp = 0b100000000000;
+        /// Attracts and is attracted by everything else with a mass, when the game has an attraction.
+        /// Also makes the entity as heavy as its mass in collisions and under forces.
+        const Mas = 0b1000000000000;
+        /// Steers by its neighbours, when the game has flocking
+        const Flk = 0b10000000000000;
+        /// Fluid particle, when the game has a fluid
+        const Fld = 0b100000000000000;
+        /// Moved by its steering behaviours
+        const Str = 0b1000000000000000;
+        /// Gives off particles
+        const Emt = 0b10000000000000000;
+        /// Despawned once its lifetime runs out
+        const Ttl = 0b100000000000000000;
+        /// Animated by its tween, until that finishes
+        const Twn = 0b1000000000000000000;
+        /// Driven by its state machine
+        const Fsm = 0b10000000000000000000;
+        /// Spawns copies of a prefab
+        const Spw = 0b100000000000000000000;
     }    
 }
 
@@ -29,9 +115,118 @@ pub struct Velocity {
     pub y : f32
 }
 
-#[derive(Default, Clone)]
+#[derive(Debug, Clone)]
+pub struct Mass {
+    pub value : f32
+}
+
+impl Default for Mass {
+    fn default() -> Self {
+        Self { value : 1.0 }
+    }
+}
+
+/// Counter-clockwise angle in radians
+#[derive(Default, Debug, Clone)]
+pub struct Orientation {
+    pub angle : f32
+}
+
+/// Radians per second, counter-clockwise
+#[derive(Default, Debug, Clone)]
+pub struct AngularVelocity {
+    pub w : f32
+}
+
+#[derive(Clone)]
 pub struct Asset {
-    texture : String
+    texture : String,
+    pub colour : [f32; 3],
+    /// 1 is the renderer's usual size
+    pub scale : f32,
+}
+
+impl Default for Asset {
+    fn default() -> Self {
+        Self { texture : String::new(), colour : [1.0, 0.0, 0.0], scale : 1.0 }
+    }
+}
+
+/// What the renderer gets to know about a shown entity
+#[derive(Clone)]
+pub struct Sprite {
+    pub asset : Asset,
+    pub position : Position,
+    /// Entities without an orientation are drawn as points
+    pub rotation : Option<f32>,
+}
+
+/// Everything the renderer gets from one update
+pub struct Frame {
+    pub sprites : Vec<Sprite>,
+    /// Tilemap chunks that changed since the last frame the renderer got
+    pub chunks : Vec<TileChunk>,
+    /// How many chunks the tilemap has. Chunks the renderer holds past this are gone.
+    pub chunk_count : usize,
+    pub particles : Vec<ParticleSprite>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Shape {
+    Circle { radius : f32 },
+    /// Axis aligned box, given by half its width and height
+    Aabb { half_width : f32, half_height : f32 },
+    /// Convex polygon around the entity's position, turned by its orientation
+    Polygon(Polygon),
+}
+
+impl Default for Shape {
+    fn default() -> Self {
+        Shape::Circle { radius : 0.0 }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Collider {
+    pub shape : Shape,
+    /// Bitmask of the layers this collider is on. Queries only see colliders sharing a layer with their filter.
+    pub layers : u32,
+    /// How much of the closing speed is kept after a collision. 0 is fully inelastic, 1 fully elastic.
+    pub restitution : f32,
+    /// Coulomb friction coefficient. Tangential impulses are what make round things spin.
+    pub friction : f32,
+}
+
+impl Collider {
+    /// Half the width and height of the collider's bounding box. Polygons get the box around
+    /// every orientation they can turn to.
+    pub fn half_extents(&self) -> (f32, f32) {
+        match &self.shape {
+            Shape::Circle { radius } => (*radius, *radius),
+            Shape::Aabb { half_width, half_height } => (*half_width, *half_height),
+            Shape::Polygon(polygon) => (polygon.bounding_radius(), polygon.bounding_radius()),
+        }
+    }
+
+    /// Moment of inertia around the centre for a body of uniform density.
+    pub fn moment_of_inertia(&self, mass : f32) -> f32 {
+        match &self.shape {
+            Shape::Circle { radius } => 0.5 * mass * radius * radius,
+            Shape::Aabb { half_width, half_height } => mass * (half_width * half_width + half_height * half_height) / 3.0,
+            Shape::Polygon(polygon) => polygon.moment_of_inertia(mass),
+        }
+    }
+}
+
+impl Default for Collider {
+    fn default() -> Self {
+        Self {
+            shape : Shape::default(),
+            layers : u32::MAX,
+            restitution : 1.0,
+            friction : 0.0,
+        }
+    }
 }
 
 pub struct Entity {
@@ -45,25 +240,123 @@ pub struct Game {
     pub velocities : Vec<Velocity>,
     pub collision_buffer_pos : Vec<Position>,
     pub collision_buffer_vel : Vec<Velocity>,
+    pub orientations : Vec<Orientation>,
+    pub angular_velocities : Vec<AngularVelocity>,
+    pub collision_buffer_ang : Vec<AngularVelocity>,
+    pub forces : Vec<Force>,
     pub assets : Vec<Asset>,
+    pub colliders : Vec<Collider>,
+    pub sensors : Vec<Sensor>,
+    /// What entered, stayed in and left each sensor during the last update
+    pub sensor_events : Vec<SensorEvent>,
+    pub constraints : Vec<Constraint>,
+    pub boundary_policies : Vec<BoundaryPolicy>,
+    pub rests : Vec<Rest>,
+    pub masses : Vec<Mass>,
+    /// Smoothed density of each fluid particle as of the last update
+    pub densities : Vec<f32>,
+    pub steerings : Vec<Steering>,
+    pub emitters : Vec<ParticleEmitter>,
+    pub lifetimes : Vec<Lifetime>,
+    pub tweenings : Vec<Tweening>,
+    /// Tweens that finished during the last update
+    pub tween_events : Vec<TweenEvent>,
+    pub state_machines : Vec<StateMachine>,
+    pub spawners : Vec<Spawner>,
+    /// The spawner each entity was spawned by, if it still lives
+    pub spawned_by : Vec<Option<EntityId>>,
+    /// Entities leaving these are handled by their boundary policy. Unbounded if None.
+    pub bounds : Option<WorldBounds>,
+    /// Pulls entities with a mass towards each other. No attraction if None.
+    pub attraction : Option<Attraction>,
+    /// Steers flocking entities. No flocking if None.
+    pub flocking : Option<Flocking>,
+    /// Simulates entities flagged as fluid particles. No fluid if None.
+    pub fluid : Option<Fluid>,
+    /// Set through set_tilemap, which keeps the tile colliders in step with it
+    tilemap : Option<Tilemap>,
+    /// Serves path requests and holds the flow fields agents follow. No pathfinding if None.
+    pub navigation : Option<Navigation>,
+    pub particles : ParticlePool,
+    /// Blueprints for spawn_prefab, referred to by their index
+    pub prefabs : Vec<Prefab>,
+    /// Machines state machine components run, referred to by their index
+    pub machines : Vec<Machine>,
+    /// Ids of despawned entities, handed out again by add_entity
+    free_ids : Vec<EntityId>,
     pub spacially_sorted : EntityGrid,
+    /// Static entities, kept apart so resting pairs among them are never looked at
+    pub static_sorted : EntityGrid,
+    pub broadphase : Broadphase,
+    pub physics : PhysicsConfig,
+    /// Hands out the random streams of systems and entities
+    pub random : Randomness,
+    /// Updates run so far
+    pub tick : u64,
+    /// Checksums of the latest updates, to catch runs going apart. No checksums if None.
+    pub checksums : Option<ChecksumLog>,
 }
 
 impl Game {
     // Dense database, CompFlag indicates if the entity has the corresponding component
     pub fn new() -> Self {
+        Self::with_broadphase(Broadphase::Grid)
+    }
+
+    pub fn with_broadphase(broadphase : Broadphase) -> Self {
         Self {
             entities : Vec::new(),
             positions : Vec::new(),
             velocities : Vec::new(),
             collision_buffer_pos : Vec::new(),
             collision_buffer_vel : Vec::new(),
+            orientations : Vec::new(),
+            angular_velocities : Vec::new(),
+            collision_buffer_ang : Vec::new(),
+            forces : Vec::new(),
             assets : Vec::new(),
+            colliders : Vec::new(),
+            sensors : Vec::new(),
+            sensor_events : Vec::new(),
+            constraints : Vec::new(),
+            boundary_policies : Vec::new(),
+            rests : Vec::new(),
+            masses : Vec::new(),
+            densities : Vec::new(),
+            steerings : Vec::new(),
+            emitters : Vec::new(),
+            lifetimes : Vec::new(),
+            tweenings : Vec::new(),
+            tween_events : Vec::new(),
+            state_machines : Vec::new(),
+            spawners : Vec::new(),
+            spawned_by : Vec::new(),
+            bounds : None,
+            attraction : None,
+            flocking : None,
+            fluid : None,
+            tilemap : None,
+            navigation : None,
+            particles : ParticlePool::default(),
+            prefabs : Vec::new(),
+            machines : Vec::new(),
+            free_ids : Vec::new(),
             spacially_sorted : EntityGrid::new(1.0),
+            static_sorted : EntityGrid::new(1.0),
+            broadphase,
+            physics : PhysicsConfig::default(),
+            random : Randomness::default(),
+            tick : 0,
+            checksums : None,
         }
     }
 
     pub fn add_entity(&mut self, components : CompFlag) -> EntityId {
+        // Despawning already reset the components of freed ids
+        if let Some(id) = self.free_ids.pop() {
+            self.entities[id as usize].components = components;
+            return id;
+        }
         let id = self.entities.len() as EntityId;
         self.entities.push(Entity {
             id,
@@ -71,76 +364,351 @@ impl Game {
         });
         self.positions.push(Position::default());
         self.velocities.push(Velocity::default());
+        self.orientations.push(Orientation::default());
+        self.angular_velocities.push(AngularVelocity::default());
+        self.forces.push(Force::default());
         self.assets.push(Asset::default());
+        self.colliders.push(Collider::default());
+        self.sensors.push(Sensor::default());
+        self.boundary_policies.push(BoundaryPolicy::default());
+        self.rests.push(Rest::default());
+        self.masses.push(Mass::default());
+        self.densities.push(0.0);
+        self.steerings.push(Steering::default());
+        self.emitters.push(ParticleEmitter::default());
+        self.lifetimes.push(Lifetime::default());
+        self.tweenings.push(Tweening::default());
+        self.state_machines.push(StateMachine::default());
+        self.spawners.push(Spawner::default());
+        self.spawned_by.push(None);
         self.collision_buffer_pos.resize(self.positions.len(), Position::default());
         self.collision_buffer_vel.resize(self.positions.len(), Velocity::default());
+        self.collision_buffer_ang.resize(self.positions.len(), AngularVelocity::default());
 
         id
     }
 
-    fn apply_veloc<'a>(physics_entities : impl Iterator<Item = (&'a Position, &'a Velocity)>) -> Vec<Position>{
-        physics_entities.map(|(pos, vel)| Position{
-            x : pos.x+vel.x, y : pos.y + vel.y
-        }).collect()
+    /// Removes the entity from the world along with anything referring to it. Its id gets reused.
+    pub fn despawn(&mut self, id : EntityId) {
+        let i = id as usize;
+        if self.free_ids.contains(&id) {
+            return;
+        }
+        self.entities[i].components = CompFlag::empty();
+        self.positions[i] = Position::default();
+        self.velocities[i] = Velocity::default();
+        self.orientations[i] = Orientation::default();
+        self.angular_velocities[i] = AngularVelocity::default();
+        self.forces[i] = Force::default();
+        self.assets[i] = Asset::default();
+        self.colliders[i] = Collider::default();
+        self.sensors[i] = Sensor::default();
+        self.boundary_policies[i] = BoundaryPolicy::default();
+        self.rests[i] = Rest::default();
+        self.masses[i] = Mass::default();
+        self.densities[i] = 0.0;
+        self.steerings[i] = Steering::default();
+        self.emitters[i] = ParticleEmitter::default();
+        self.lifetimes[i] = Lifetime::default();
+        self.tweenings[i] = Tweening::default();
+        self.state_machines[i] = StateMachine::default();
+        self.spawners[i] = Spawner::default();
+        self.spawned_by[i] = None;
+
+        self.constraints.retain(|c| c.a != id && c.b != id);
+        for sensor in &mut self.sensors {
+            sensor.inside.retain(|x| *x != id);
+        }
+        for steering in &mut self.steerings {
+            steering.forget(id);
+        }
+        self.spacially_sorted.remove(id);
+        self.static_sorted.remove(id);
+        self.free_ids.push(id);
     }
 
-    fn collide<'a>(physics_entities : impl Iterator<Item = EntityId>, spacially_sorted : &EntityGrid, positions : &[Position], velocities : &[Velocity], pos_buffer : &mut [Position], vel_buffer : &mut [Velocity]) {
-        
-        let iterator = physics_entities.map(|i| {
-            let pos = &positions[i as usize];
-            let vel = &velocities[i as usize];
+    /// Resizes the cells of the grids entities are sorted into. The grid broadphase and casts only
+    /// look at neighbouring cells, so colliders shouldn't be larger than a cell.
+    /// Everything gets sorted in again on the next update.
+    pub fn set_cell_size(&mut self, size : f32) {
+        self.spacially_sorted = EntityGrid::new(size);
+        self.static_sorted = EntityGrid::new(size);
+    }
+
+    fn apply_veloc<'a>(physics_entities : impl Iterator<Item = (&'a Position, &'a Velocity, glm::Vec2)>, physics : &PhysicsConfig) -> Vec<(Position, Velocity)>{
+        let dt = physics.time_step;
+        physics_entities.map(|(pos, vel, acc)| physics.integrator.begin(pos, vel, &acc, dt)).collect()
+    }
 
-            let nearby_entities = spacially_sorted.find_nearby(&pos);
-            match nearby_entities {
-                None => (),
-                Some(r) => (
-                    for nearby_entity in r.iter() {
-                        
-                    }
-                     //TODO: Collision detection
-                )
+    /// Finishes the integrator's step once the entities were moved, then damps their velocities.
+    /// attracted is the attraction each body got from where it started, as returned by attract,
+    /// which is swapped for the attraction from where it ended up.
+    fn finish_step(&mut self, physics_entities : &[EntityId], attracted : &[glm::Vec2]) {
+        let dt = self.physics.time_step;
+        if self.physics.integrator.resamples() {
+            let attracting = if attracted.is_empty() { Vec::new() } else { self.approximate_attraction() };
+            for &id in physics_entities {
+                let i = id as usize;
+                let mut acc = self.acceleration(id);
+                if !attracting.is_empty() && self.entities[i].components.contains(CompFlag::Mas) {
+                    acc += attracting[i] - attracted[i];
+                }
+                self.velocities[i] = self.physics.integrator.finish(&self.velocities[i], &acc, dt);
             }
+        }
+        let damping = 1.0 / (1.0 + self.physics.linear_damping * dt);
+        for &id in physics_entities {
+            let vel = &mut self.velocities[id as usize];
+            vel.x *= damping;
+            vel.y *= damping;
+        }
+    }
 
-            (pos.clone(), vel.clone())
-        });
-        for (i,j) in iterator.enumerate() {
-            pos_buffer[i] = j.0;
-            vel_buffer[i] = j.1;
+    /// Gravity plus whatever has been accumulated in the entity's force, over its mass.
+    /// Attraction accumulates into the force of entities with a mass, even without Frc.
+    fn acceleration(&self, id : EntityId) -> glm::Vec2 {
+        let mut acc = self.physics.gravity;
+        if self.entities[id as usize].components.intersects(CompFlag::Frc | CompFlag::Mas) {
+            let force = &self.forces[id as usize];
+            acc += glm::vec2(force.x, force.y) * self.inverse_mass(id);
+        }
+        acc
+    }
+
+    /// Spins entities by their torque and angular velocity. The torque lives in the force accumulator.
+    fn apply_spin(&mut self) {
+        let dt = self.physics.time_step;
+        let damping = 1.0 / (1.0 + self.physics.angular_damping * dt);
+        for entity in self.entities.iter().filter(|x| x.components.contains(CompFlag::Rot | CompFlag::Ang) && !x.components.intersects(CompFlag::Sta | CompFlag::Slp)) {
+            let id = entity.id as usize;
+            let inv_inertia = self.inverse_inertia(entity.id);
+            let spin = &mut self.angular_velocities[id];
+            if entity.components.contains(CompFlag::Frc) {
+                spin.w += self.forces[id].torque * inv_inertia * dt;
+            }
+            spin.w *= damping;
+            self.orientations[id].angle += spin.w * dt;
         }
     }
 
-    pub fn update(&mut self, wd_sender : &mut SyncSender<Vec<(Asset, Position)>>) {
-        let physics_entities = self.entities.iter()
-            .filter(|x| x.components.contains(CompFlag::Pos | CompFlag::Vel))
-            .map(|x| &x.id);
+    /// The entity's orientation, or zero if it doesn't have one.
+    fn angle(&self, id : EntityId) -> f32 {
+        if self.entities[id as usize].components.contains(CompFlag::Rot) { self.orientations[id as usize].angle } else { 0.0 }
+    }
+
+    fn inverse_mass(&self, id : EntityId) -> f32 {
+        let components = self.entities[id as usize].components;
+        if !components.contains(CompFlag::Vel) || components.intersects(CompFlag::Sta | CompFlag::Slp) {
+            0.0
+        } else if components.contains(CompFlag::Mas) {
+            1.0 / self.masses[id as usize].value
+        } else {
+            1.0
+        }
+    }
+
+    /// Zero for anything that can't spin, or whose collider is a point.
+    fn inverse_inertia(&self, id : EntityId) -> f32 {
+        if !self.entities[id as usize].components.contains(CompFlag::Rot | CompFlag::Ang) {
+            return 0.0;
+        }
+        let inv_mass = self.inverse_mass(id);
+        let inertia = if inv_mass > 0.0 { self.colliders[id as usize].moment_of_inertia(1.0 / inv_mass) } else { 0.0 };
+        if inertia > 0.0 { 1.0 / inertia } else { 0.0 }
+    }
+
+    /// Pushes apart overlapping candidate pairs and bounces their velocities off each other.
+    /// Impulses act at the contact point, so hits off the centre of mass also change the spin of
+    /// entities that can rotate.
+    /// Reads from positions and velocities and accumulates the corrections into the collision buffers,
+    /// which have to start out as copies of them. Entities without a velocity don't budge, and neither
+    /// do static or sleeping ones, though a sleeper gets woken by whatever hits it.
+    fn collide(&mut self, pairs : &[(EntityId, EntityId)]) {
+        // Found up front, so they can be resolved deepest first. Otherwise a contact barely
+        // grazing the corner of a box could turn the whole closing velocity sideways before the
+        // box actually underneath gets to stop it.
+        let mut contacts = Vec::new();
+        for &(a, b) in pairs {
+            let (ia, ib) = (a as usize, b as usize);
+            if (self.entities[ia].components | self.entities[ib].components).contains(CompFlag::Sen) {
+                continue;
+            }
+            let (ca, cb) = (&self.colliders[ia], &self.colliders[ib]);
+            if ca.layers & cb.layers == 0 {
+                continue;
+            }
+            if let Some(contact) = collision::contact(&self.positions[ia], self.angle(a), &ca.shape, &self.positions[ib], self.angle(b), &cb.shape) {
+                contacts.push((a, b, contact));
+            }
+        }
+        contacts.sort_by(|x, y| y.2.depth.total_cmp(&x.2.depth));
+
+        for (a, b, contact) in contacts {
+            let (ia, ib) = (a as usize, b as usize);
+            self.wake_on_contact(a, b);
+            let (ca, cb) = (&self.colliders[ia], &self.colliders[ib]);
+
+            let (inv_a, inv_b) = (self.inverse_mass(a), self.inverse_mass(b));
+            let inv_sum = inv_a + inv_b;
+            if inv_sum == 0.0 {
+                continue;
+            }
+            let (inv_ia, inv_ib) = (self.inverse_inertia(a), self.inverse_inertia(b));
+            let n = contact.normal;
+
+            // Against something immovable, contacts are resolved one after the other from the
+            // corrected buffers, so a body touching a row of boxes, like tiles, isn't pushed and
+            // bounced once by each of them
+            let sequential = inv_a == 0.0 || inv_b == 0.0;
+            let depth = if sequential {
+                let moved = |i : usize| glm::vec2(self.collision_buffer_pos[i].x - self.positions[i].x, self.collision_buffer_pos[i].y - self.positions[i].y);
+                let resolved = if inv_a > 0.0 { -moved(ia).dot(&n) } else { moved(ib).dot(&n) };
+                (contact.depth - resolved).max(0.0)
+            } else {
+                contact.depth
+            };
+            let push = n * depth / inv_sum;
+            self.collision_buffer_pos[ia].x -= push.x * inv_a;
+            self.collision_buffer_pos[ia].y -= push.y * inv_a;
+            self.collision_buffer_pos[ib].x += push.x * inv_b;
+            self.collision_buffer_pos[ib].y += push.y * inv_b;
+
+            let cross = |r : &glm::Vec2, d : &glm::Vec2| r.x * d.y - r.y * d.x;
+            let restitution = ca.restitution.min(cb.restitution);
+            let friction = (ca.friction * cb.friction).sqrt();
+            // Every point of the manifold takes its share of the impulse
+            let share = 1.0 / contact.points().len() as f32;
+            for point in contact.points() {
+                // Velocity of each body at the contact point, including what its spin contributes
+                let ra = point - glm::vec2(self.positions[ia].x, self.positions[ia].y);
+                let rb = point - glm::vec2(self.positions[ib].x, self.positions[ib].y);
+                let (velocities, spins) = if sequential { (&self.collision_buffer_vel, &self.collision_buffer_ang) } else { (&self.velocities, &self.angular_velocities) };
+                let (wa, wb) = (spins[ia].w, spins[ib].w);
+                let va = glm::vec2(velocities[ia].x - wa * ra.y, velocities[ia].y + wa * ra.x);
+                let vb = glm::vec2(velocities[ib].x - wb * rb.y, velocities[ib].y + wb * rb.x);
+                let relative = vb - va;
+                let closing = relative.dot(&n);
+                if closing >= 0.0 {
+                    continue;
+                }
 
-        let b = physics_entities.clone().map(|i| (&self.positions[*i as usize], &self.velocities[*i as usize]));
+                let effective_mass = |d : &glm::Vec2| inv_sum + cross(&ra, d).powi(2) * inv_ia + cross(&rb, d).powi(2) * inv_ib;
 
-        let new_positions = Game::apply_veloc(b);
-        for (i, j) in physics_entities.zip(new_positions) {
-            self.positions[*i as usize] = j
+                let j = -(1.0 + restitution) * closing / effective_mass(&n) * share;
+                let mut impulse = n * j;
+
+                let tangent = relative - n * closing;
+                if tangent.norm_squared() > 0.0 {
+                    let t = tangent.normalize();
+                    let jt = (-relative.dot(&t) / effective_mass(&t) * share).max(-friction * j).min(friction * j);
+                    impulse += t * jt;
+                }
+
+                self.collision_buffer_vel[ia].x -= impulse.x * inv_a;
+                self.collision_buffer_vel[ia].y -= impulse.y * inv_a;
+                self.collision_buffer_vel[ib].x += impulse.x * inv_b;
+                self.collision_buffer_vel[ib].y += impulse.y * inv_b;
+                self.collision_buffer_ang[ia].w -= cross(&ra, &impulse) * inv_ia;
+                self.collision_buffer_ang[ib].w += cross(&rb, &impulse) * inv_ib;
+            }
         }
+    }
+
+    pub fn update(&mut self, wd_sender : &mut SyncSender<Frame>) {
+        self.expire_lifetimes();
+        self.run_spawners();
+        self.update_tweens();
+        self.update_state_machines();
+        let attracted = self.attract();
+        self.wake_disturbed();
+        self.flock();
+        self.serve_path_requests();
+        self.steer();
+        self.simulate_fluid();
+        let physics_entities : Vec<EntityId> = self.entities.iter()
+            .filter(|x| x.components.contains(CompFlag::Pos | CompFlag::Vel) && !x.components.intersects(CompFlag::Sta | CompFlag::Slp))
+            .map(|x| x.id)
+            .collect();
 
-        
-        let physics_entities = self.entities.iter()
-            .filter(|x| x.components.contains(CompFlag::Pos | CompFlag::Vel))
-            .map(|x| x.id);
+        let b = physics_entities.iter().map(|i| (&self.positions[*i as usize], &self.velocities[*i as usize], self.acceleration(*i)));
 
-        Game::collide(physics_entities.clone(), &self.spacially_sorted, &self.positions, &self.velocities, &mut self.collision_buffer_pos, &mut self.collision_buffer_vel);
+        let stepped = Game::apply_veloc(b, &self.physics);
+        let swept = self.sweep_fast_movers(physics_entities.iter().copied().zip(stepped.iter().map(|x| &x.0)));
+        for (i, (pos, vel)) in physics_entities.iter().zip(stepped) {
+            self.positions[*i as usize] = pos;
+            self.velocities[*i as usize] = vel;
+        }
+        for (i, j) in swept {
+            self.positions[i as usize] = j;
+        }
+        self.finish_step(&physics_entities, &attracted);
+        self.apply_spin();
+        self.solve_constraints();
+        for force in self.forces.iter_mut() {
+            *force = Force::default();
+        }
+        // Before sorting, so the grid only ever sees cells inside the world
+        self.apply_bounds();
 
+        // Everything positioned goes in a grid, so queries also see entities that don't move.
+        // Static entities only get moved again when something outside the game teleports them.
+        for entity in self.entities.iter().filter(|x| x.components.contains(CompFlag::Pos)) {
+            let pos = &self.positions[entity.id as usize];
+            if entity.components.contains(CompFlag::Sta) {
+                self.spacially_sorted.remove(entity.id);
+                self.static_sorted.sort_single(entity.id, pos);
+            } else {
+                self.static_sorted.remove(entity.id);
+                self.spacially_sorted.sort_single(entity.id, pos);
+            }
+        }
 
-        for (i, j) in physics_entities.zip(&self.collision_buffer_pos) {
-            self.spacially_sorted.sort_single(i, &j);
+        // Sensors count as awake, so they keep seeing what sleeps inside them
+        let mut awake : Vec<EntityId> = Vec::new();
+        let mut resting : Vec<EntityId> = Vec::new();
+        for entity in self.entities.iter().filter(|x| x.components.contains(CompFlag::Pos | CompFlag::Col)) {
+            if entity.components.intersects(CompFlag::Sta | CompFlag::Slp) && !entity.components.contains(CompFlag::Sen) {
+                resting.push(entity.id);
+            } else {
+                awake.push(entity.id);
+            }
         }
+        let pairs = self.broadphase.find_pairs(&awake, &resting, &self.spacially_sorted, &self.static_sorted, &self.positions, &self.colliders);
+        self.update_sensors(&pairs);
+
+        // Entities that aren't simulated still have to survive the buffer swap
+        self.collision_buffer_pos.clone_from_slice(&self.positions);
+        self.collision_buffer_vel.clone_from_slice(&self.velocities);
+        self.collision_buffer_ang.clone_from_slice(&self.angular_velocities);
+        self.collide(&pairs);
+
         std::mem::swap(&mut self.positions, &mut self.collision_buffer_pos);
         std::mem::swap(&mut self.velocities, &mut self.collision_buffer_vel);
-        
+        std::mem::swap(&mut self.angular_velocities, &mut self.collision_buffer_ang);
+        self.update_sleep();
+        self.update_particles();
+        self.finish_tick();
 
-        let shown_entities = self.entities.iter()
+        let sprites = self.entities.iter()
             .filter(|x| x.components.contains(CompFlag::Pos | CompFlag::Ass))
-            .map(|x| (self.assets[x.id as usize].clone(), self.positions[x.id as usize].clone()))
+            .map(|x| Sprite {
+                asset : self.assets[x.id as usize].clone(),
+                position : self.positions[x.id as usize].clone(),
+                rotation : if x.components.contains(CompFlag::Rot) { Some(self.orientations[x.id as usize].angle) } else { None },
+            })
             .collect();
-        let _ = wd_sender.try_send(shown_entities);
+        let (chunks, chunk_count) = match &mut self.tilemap {
+            Some(t) => (t.take_dirty_chunks(), t.chunk_count()),
+            None => (Vec::new(), 0),
+        };
+        // A dropped frame's chunks go out again with the next one
+        if let Err(TrySendError::Full(frame)) | Err(TrySendError::Disconnected(frame)) = wd_sender.try_send(Frame { sprites, chunks, chunk_count, particles : self.particles.sprites() }) {
+            if let Some(t) = &mut self.tilemap {
+                for chunk in frame.chunks {
+                    t.mark_dirty(chunk.index);
+                }
+            }
+        }
 
     }
 }
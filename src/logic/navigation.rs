@@ -0,0 +1,496 @@
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+
+use super::{CompFlag, Game, Position, Tilemap};
+use super::snapshot::{Reader, Save, Writer};
+
+pub type PathRequestId = u32;
+
+/// Walkable and blocked cells, laid out like a tilemap with its bottom left corner at origin.
+#[derive(Debug, Clone)]
+pub struct NavGrid {
+    pub origin : glm::Vec2,
+    pub cell_size : f32,
+    width : usize,
+    height : usize,
+    blocked : Vec<bool>,
+}
+
+/// Orthogonal steps cost 1 and diagonal ones the square root of 2
+const DIAGONAL : f32 = std::f32::consts::SQRT_2;
+
+const STEPS : [(isize, isize); 8] = [(-1, 0), (1, 0), (0, -1), (0, 1), (-1, -1), (1, -1), (-1, 1), (1, 1)];
+
+impl NavGrid {
+    /// Everything walkable
+    pub fn new(origin : glm::Vec2, cell_size : f32, width : usize, height : usize) -> Self {
+        Self { origin, cell_size, width, height, blocked : vec![false; width * height] }
+    }
+
+    /// One cell per tile, blocked where the tile blocks.
+    pub fn from_tilemap(tilemap : &Tilemap) -> Self {
+        let mut grid = Self::new(tilemap.origin, tilemap.tile_size, tilemap.width(), tilemap.height());
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                grid.blocked[y * grid.width + x] = tilemap.is_blocking(x, y);
+            }
+        }
+        grid
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn set_blocked(&mut self, x : usize, y : usize, blocked : bool) {
+        self.blocked[y * self.width + x] = blocked;
+    }
+
+    /// Whether the cell is in the grid and not blocked.
+    pub fn is_walkable(&self, x : usize, y : usize) -> bool {
+        x < self.width && y < self.height && !self.blocked[y * self.width + x]
+    }
+
+    /// Column and row of the cell covering position, or None outside the grid.
+    pub fn cell_at(&self, position : &Position) -> Option<(usize, usize)> {
+        let x = ((position.x - self.origin.x) / self.cell_size).floor();
+        let y = ((position.y - self.origin.y) / self.cell_size).floor();
+        if x < 0.0 || y < 0.0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+
+    pub fn cell_centre(&self, x : usize, y : usize) -> glm::Vec2 {
+        self.origin + glm::vec2(x as f32 + 0.5, y as f32 + 0.5) * self.cell_size
+    }
+
+    /// Whether the straight line between two points only crosses walkable cells. Checked at
+    /// every quarter cell along it.
+    pub fn line_of_sight(&self, from : &glm::Vec2, to : &glm::Vec2) -> bool {
+        let steps = ((to - from).norm() / self.cell_size * 4.0).ceil() as usize;
+        (0..=steps).all(|i| {
+            let p = from + (to - from) * (i as f32 / steps.max(1) as f32);
+            self.cell_at(&Position { x : p.x, y : p.y }).is_some_and(|(x, y)| self.is_walkable(x, y))
+        })
+    }
+
+    fn centre_of(&self, i : usize) -> glm::Vec2 {
+        self.cell_centre(i % self.width, i / self.width)
+    }
+
+    /// Walkable cells one step from cell i, with what stepping there costs. Diagonal steps need
+    /// both cells they squeeze between to be walkable, so paths don't cut corners.
+    fn neighbours(&self, i : usize) -> impl Iterator<Item = (usize, f32)> + '_ {
+        let (x, y) = ((i % self.width) as isize, (i / self.width) as isize);
+        let walkable = move |dx : isize, dy : isize| {
+            let (nx, ny) = (x + dx, y + dy);
+            nx >= 0 && ny >= 0 && self.is_walkable(nx as usize, ny as usize)
+        };
+        STEPS.iter().copied()
+            .filter(move |(dx, dy)| walkable(*dx, *dy) && (*dx == 0 || *dy == 0 || (walkable(*dx, 0) && walkable(0, *dy))))
+            .map(move |(dx, dy)| (((y + dy) as usize) * self.width + (x + dx) as usize, if dx != 0 && dy != 0 { DIAGONAL } else { 1.0 }))
+    }
+
+    /// Octile distance, exact on an open grid.
+    fn heuristic(&self, a : usize, b : usize) -> f32 {
+        let dx = (a % self.width).abs_diff(b % self.width) as f32;
+        let dy = (a / self.width).abs_diff(b / self.width) as f32;
+        dx.max(dy) + (DIAGONAL - 1.0) * dx.min(dy)
+    }
+
+    /// A* from one position to another, done right away. The path runs through the centres of
+    /// the cells where it turns and ends on to. None if either end is blocked or outside the grid,
+    /// or there's no way between them.
+    pub fn find_path(&self, from : &Position, to : &Position) -> Option<Vec<glm::Vec2>> {
+        let mut search = Search::new(self, 0, from, to).ok()?;
+        let mut budget = usize::MAX;
+        search.step(self, &mut budget).unwrap()
+    }
+
+    /// Distances to goal from every cell, for any number of agents heading there.
+    pub fn flow_field(&self, goal : &Position) -> Option<FlowField> {
+        let (gx, gy) = self.cell_at(goal).filter(|(x, y)| self.is_walkable(*x, *y))?;
+        let start = gy * self.width + gx;
+        let mut distances = vec![f32::INFINITY; self.width * self.height];
+        let mut open = BinaryHeap::new();
+        distances[start] = 0.0;
+        open.push(Open { estimate : 0.0, cell : start });
+        // Dijkstra outwards from the goal
+        while let Some(Open { estimate, cell }) = open.pop() {
+            if estimate > distances[cell] {
+                continue;
+            }
+            for (next, cost) in self.neighbours(cell) {
+                if distances[cell] + cost < distances[next] {
+                    distances[next] = distances[cell] + cost;
+                    open.push(Open { estimate : distances[next], cell : next });
+                }
+            }
+        }
+
+        // Every cell points at the neighbour closest to the goal
+        let directions = (0..distances.len())
+            .map(|i| {
+                let best = self.neighbours(i).min_by(|a, b| (distances[a.0] + a.1).total_cmp(&(distances[b.0] + b.1)));
+                match best {
+                    Some((next, _)) if distances[i].is_finite() && i != start => (self.centre_of(next) - self.centre_of(i)).normalize(),
+                    _ => glm::Vec2::zeros(),
+                }
+            })
+            .collect();
+        Some(FlowField { goal : glm::vec2(goal.x, goal.y), goal_cell : start, grid : self.clone(), distances, directions })
+    }
+}
+
+/// Which way to go from anywhere on a nav grid to reach one goal.
+#[derive(Debug, Clone)]
+pub struct FlowField {
+    pub goal : glm::Vec2,
+    goal_cell : usize,
+    grid : NavGrid,
+    distances : Vec<f32>,
+    directions : Vec<glm::Vec2>,
+}
+
+impl FlowField {
+    /// Unit direction to head in from position. Shorter within the goal's cell, so agents ease
+    /// onto it. Zero outside the grid and where the goal can't be reached from.
+    pub fn direction(&self, position : &Position) -> glm::Vec2 {
+        let (x, y) = match self.grid.cell_at(position) {
+            Some(c) => c,
+            None => return glm::Vec2::zeros(),
+        };
+        let i = y * self.grid.width + x;
+        if i == self.goal_cell {
+            let d = (self.goal - glm::vec2(position.x, position.y)) / self.grid.cell_size;
+            return if d.norm() > 1.0 { d.normalize() } else { d };
+        }
+        if self.distances[i].is_finite() {
+            return self.directions[i];
+        }
+        // Crowds and collisions can shove agents into blocked cells, which lead back out to
+        // whichever neighbour is closest to the goal
+        let (x, y) = (x as isize, y as isize);
+        let closest = STEPS.iter()
+            .map(|(dx, dy)| (x + dx, y + dy))
+            .filter(|(nx, ny)| *nx >= 0 && *ny >= 0 && (*nx as usize) < self.grid.width && (*ny as usize) < self.grid.height)
+            .map(|(nx, ny)| (nx as usize, ny as usize))
+            .filter(|(nx, ny)| self.distances[ny * self.grid.width + nx].is_finite())
+            .min_by(|a, b| self.distances[a.1 * self.grid.width + a.0].total_cmp(&self.distances[b.1 * self.grid.width + b.0]));
+        match closest {
+            Some((nx, ny)) => (self.grid.cell_centre(nx, ny) - glm::vec2(position.x, position.y)).normalize(),
+            None => glm::Vec2::zeros(),
+        }
+    }
+
+    /// How far the goal is along the grid, in cells. None where it can't be reached from.
+    pub fn distance(&self, position : &Position) -> Option<f32> {
+        let (x, y) = self.grid.cell_at(position)?;
+        Some(self.distances[y * self.grid.width + x]).filter(|d| d.is_finite())
+    }
+}
+
+/// Entry of the open set, ordered so the heap pops the lowest estimate first.
+#[derive(PartialEq)]
+struct Open {
+    estimate : f32,
+    cell : usize,
+}
+
+impl Eq for Open {}
+
+impl Ord for Open {
+    fn cmp(&self, other : &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate).then_with(|| other.cell.cmp(&self.cell))
+    }
+}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other : &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// An A* search that can be stopped after expanding some cells and picked up again later.
+struct Search {
+    id : PathRequestId,
+    goal : usize,
+    end : glm::Vec2,
+    open : BinaryHeap<Open>,
+    costs : Vec<f32>,
+    came_from : Vec<usize>,
+}
+
+impl Search {
+    /// Err with the id if the search is over before it starts.
+    fn new(grid : &NavGrid, id : PathRequestId, from : &Position, to : &Position) -> Result<Self, PathRequestId> {
+        let walkable = |c : &(usize, usize)| grid.is_walkable(c.0, c.1);
+        let (start, goal) = match (grid.cell_at(from).filter(walkable), grid.cell_at(to).filter(walkable)) {
+            (Some(s), Some(g)) => (s.1 * grid.width + s.0, g.1 * grid.width + g.0),
+            _ => return Err(id),
+        };
+        let mut costs = vec![f32::INFINITY; grid.width * grid.height];
+        costs[start] = 0.0;
+        let mut open = BinaryHeap::new();
+        open.push(Open { estimate : grid.heuristic(start, goal), cell : start });
+        Ok(Self { id, goal, end : glm::vec2(to.x, to.y), open, costs, came_from : vec![usize::MAX; grid.width * grid.height] })
+    }
+
+    /// Expands cells until the search is over or the budget is spent, taking what it used from
+    /// the budget. Some with the path, or None if there's none, once it's over.
+    fn step(&mut self, grid : &NavGrid, budget : &mut usize) -> Option<Option<Vec<glm::Vec2>>> {
+        while *budget > 0 {
+            let Open { estimate, cell } = match self.open.pop() {
+                Some(o) => o,
+                None => return Some(None),
+            };
+            if cell == self.goal {
+                return Some(Some(self.path(grid)));
+            }
+            // Already expanded on a cheaper route
+            if estimate > self.costs[cell] + grid.heuristic(cell, self.goal) {
+                continue;
+            }
+            *budget -= 1;
+            for (next, cost) in grid.neighbours(cell) {
+                let through = self.costs[cell] + cost;
+                if through < self.costs[next] {
+                    self.costs[next] = through;
+                    self.came_from[next] = cell;
+                    self.open.push(Open { estimate : through + grid.heuristic(next, self.goal), cell : next });
+                }
+            }
+        }
+        None
+    }
+
+    /// Walks back from the goal, keeping only the cells the path turns at.
+    fn path(&self, grid : &NavGrid) -> Vec<glm::Vec2> {
+        let mut cells = vec![self.goal];
+        while let Some(&previous) = cells.last().map(|c| &self.came_from[*c]).filter(|c| **c != usize::MAX) {
+            cells.push(previous);
+        }
+        cells.reverse();
+        let step = |a : usize, b : usize| ((b % grid.width) as isize - (a % grid.width) as isize, (b / grid.width) as isize - (a / grid.width) as isize);
+        let mut path : Vec<glm::Vec2> = (1..cells.len().saturating_sub(1))
+            .filter(|i| step(cells[i - 1], cells[*i]) != step(cells[*i], cells[i + 1]))
+            .map(|i| grid.centre_of(cells[i]))
+            .collect();
+        path.push(self.end);
+        path
+    }
+}
+
+/// A path that was asked for with request_path.
+#[derive(Debug, Clone)]
+pub struct PathResult {
+    pub id : PathRequestId,
+    /// Same as find_path's
+    pub path : Option<Vec<glm::Vec2>>,
+}
+
+/// Pathfinding over a nav grid. A* requests are queued and served a few cells at a time every
+/// update, so lots of them at once don't stall it.
+pub struct Navigation {
+    pub grid : NavGrid,
+    /// Most cells all searches together expand in one update
+    pub budget : usize,
+    /// Fields the FollowFlow behaviour follows, by index
+    pub flow_fields : Vec<FlowField>,
+    /// Paths finished during the last update
+    pub results : Vec<PathResult>,
+    requests : VecDeque<(PathRequestId, Position, Position)>,
+    search : Option<Search>,
+    next_id : PathRequestId,
+}
+
+impl Navigation {
+    pub fn new(grid : NavGrid) -> Self {
+        Self {
+            grid,
+            budget : 2000,
+            flow_fields : Vec::new(),
+            results : Vec::new(),
+            requests : VecDeque::new(),
+            search : None,
+            next_id : 0,
+        }
+    }
+
+    /// Queues an A* search. Its result shows up in results at the end of whichever update finishes it.
+    pub fn request_path(&mut self, from : &Position, to : &Position) -> PathRequestId {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.requests.push_back((id, from.clone(), to.clone()));
+        id
+    }
+
+    /// Builds a flow field towards goal for FollowFlow to use, and returns its index. None if the
+    /// goal isn't on a walkable cell.
+    pub fn add_flow_field(&mut self, goal : &Position) -> Option<usize> {
+        let field = self.grid.flow_field(goal)?;
+        self.flow_fields.push(field);
+        Some(self.flow_fields.len() - 1)
+    }
+
+    pub fn pending_requests(&self) -> usize {
+        self.requests.len() + self.search.is_some() as usize
+    }
+}
+
+impl Game {
+    /// A grid covering width by height cells from origin, with every cell blocked that comes
+    /// within clearance of a static collider's bounding box. The clearance is usually the radius
+    /// of whatever walks the grid.
+    pub fn nav_grid_from_colliders(&self, origin : glm::Vec2, cell_size : f32, width : usize, height : usize, clearance : f32) -> NavGrid {
+        let mut grid = NavGrid::new(origin, cell_size, width, height);
+        let solid = self.entities.iter()
+            .filter(|x| x.components.contains(CompFlag::Pos | CompFlag::Col | CompFlag::Sta) && !x.components.contains(CompFlag::Sen));
+        for entity in solid {
+            let pos = &self.positions[entity.id as usize];
+            let (hw, hh) = self.colliders[entity.id as usize].half_extents();
+            let cell = |v : f32, o : f32| ((v - o) / cell_size).floor();
+            // Cells touched by the box grown by the clearance, clamped to the grid
+            let (x0, x1) = (cell(pos.x - hw - clearance, origin.x).max(0.0), cell(pos.x + hw + clearance, origin.x).min(width as f32 - 1.0));
+            let (y0, y1) = (cell(pos.y - hh - clearance, origin.y).max(0.0), cell(pos.y + hh + clearance, origin.y).min(height as f32 - 1.0));
+            if x0 > x1 || y0 > y1 {
+                continue;
+            }
+            for y in y0 as usize..=y1 as usize {
+                for x in x0 as usize..=x1 as usize {
+                    grid.set_blocked(x, y, true);
+                }
+            }
+        }
+        grid
+    }
+
+    /// Works through queued path requests until the update's budget is spent. A search that
+    /// doesn't finish carries on where it left off next update.
+    pub(super) fn serve_path_requests(&mut self) {
+        let navigation = match &mut self.navigation {
+            Some(n) => n,
+            None => return,
+        };
+        navigation.results.clear();
+        let mut budget = navigation.budget;
+        while budget > 0 {
+            if navigation.search.is_none() {
+                let (id, from, to) = match navigation.requests.pop_front() {
+                    Some(r) => r,
+                    None => return,
+                };
+                match Search::new(&navigation.grid, id, &from, &to) {
+                    Ok(search) => navigation.search = Some(search),
+                    Err(id) => {
+                        navigation.results.push(PathResult { id, path : None });
+                        continue;
+                    },
+                }
+            }
+            let search = navigation.search.as_mut().unwrap();
+            if let Some(path) = search.step(&navigation.grid, &mut budget) {
+                navigation.results.push(PathResult { id : search.id, path });
+                navigation.search = None;
+            }
+        }
+    }
+}
+
+impl Save for NavGrid {
+    fn save(&self, w : &mut Writer) {
+        (self.origin, self.cell_size).save(w);
+        (self.width, self.height).save(w);
+        self.blocked.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (origin, cell_size) = Save::load(r)?;
+        let (width, height) = Save::load(r)?;
+        let blocked : Vec<bool> = Save::load(r)?;
+        if Some(blocked.len()) != usize::checked_mul(width, height) {
+            return Err(format!("Snapshot has {} nav cells for a {} by {} grid", blocked.len(), width, height));
+        }
+        Ok(Self { origin, cell_size, width, height, blocked })
+    }
+}
+
+impl Save for FlowField {
+    fn save(&self, w : &mut Writer) {
+        (self.goal, self.goal_cell).save(w);
+        self.grid.save(w);
+        self.distances.save(w);
+        self.directions.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (goal, goal_cell) = Save::load(r)?;
+        let field = Self { goal, goal_cell, grid : Save::load(r)?, distances : Save::load(r)?, directions : Save::load(r)? };
+        let cells = field.grid.blocked.len();
+        if field.distances.len() != cells || field.directions.len() != cells {
+            return Err(format!("Snapshot has a flow field with {} distances and {} directions over {} cells", field.distances.len(), field.directions.len(), cells));
+        }
+        Ok(field)
+    }
+}
+
+/// The open set goes in heap order, which rebuilding the heap from leaves as it is.
+impl Save for Search {
+    fn save(&self, w : &mut Writer) {
+        (self.id, self.goal, self.end).save(w);
+        self.open.len().save(w);
+        for open in self.open.iter() {
+            (open.estimate, open.cell).save(w);
+        }
+        self.costs.save(w);
+        self.came_from.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (id, goal, end) = Save::load(r)?;
+        let open : Vec<(f32, usize)> = Save::load(r)?;
+        let open = open.into_iter().map(|(estimate, cell)| Open { estimate, cell }).collect::<Vec<_>>().into();
+        Ok(Self { id, goal, end, open, costs : Save::load(r)?, came_from : Save::load(r)? })
+    }
+}
+
+impl Save for Navigation {
+    fn save(&self, w : &mut Writer) {
+        self.grid.save(w);
+        self.budget.save(w);
+        self.flow_fields.save(w);
+        self.results.save(w);
+        self.requests.save(w);
+        self.search.save(w);
+        self.next_id.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let navigation = Self {
+            grid : Save::load(r)?,
+            budget : Save::load(r)?,
+            flow_fields : Save::load(r)?,
+            results : Save::load(r)?,
+            requests : Save::load(r)?,
+            search : Save::load(r)?,
+            next_id : Save::load(r)?,
+        };
+        // Every cell the search holds on to has to be one of the grid's
+        if let Some(search) = &navigation.search {
+            let cells = navigation.grid.blocked.len();
+            let outside = search.goal >= cells
+                || search.open.iter().any(|x| x.cell >= cells)
+                || search.came_from.iter().any(|x| *x >= cells && *x != usize::MAX);
+            if search.costs.len() != cells || search.came_from.len() != cells || outside {
+                return Err(format!("Snapshot has a path search that doesn't fit its {} nav cells", cells));
+            }
+        }
+        Ok(navigation)
+    }
+}
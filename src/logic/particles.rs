@@ -0,0 +1,234 @@
+
+use rand::Rng;
+
+use super::{Checksum, CompFlag, Game, Stream};
+use super::snapshot::{Reader, Save, Writer};
+
+/// Spawns particles from its entity's position. Every random pick comes from its own seeded
+/// generator, so the same seed always gives off the same particles.
+#[derive(Debug, Clone)]
+pub struct ParticleEmitter {
+    /// Particles per second
+    pub rate : f32,
+    /// Counter-clockwise angle particles head off in, in radians
+    pub direction : f32,
+    /// How far either side of the direction particles can stray, in radians
+    pub spread : f32,
+    /// Lowest and highest starting speed
+    pub speed : (f32, f32),
+    /// Shortest and longest lifetime in seconds
+    pub lifetime : (f32, f32),
+    /// Colour at birth and at death, blended in between
+    pub colour : ([f32; 3], [f32; 3]),
+    /// Point size in pixels at birth and at death
+    pub size : (f32, f32),
+    /// Pulls on the particles, like gravity or wind
+    pub acceleration : glm::Vec2,
+    rng : Stream,
+    /// Fractions of a particle owed from earlier updates
+    owed : f32,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl ParticleEmitter {
+    pub fn new(seed : u64) -> Self {
+        Self {
+            rate : 100.0,
+            direction : std::f32::consts::FRAC_PI_2,
+            spread : std::f32::consts::PI,
+            speed : (0.1, 0.3),
+            lifetime : (0.5, 1.0),
+            colour : ([1.0, 1.0, 0.5], [1.0, 0.0, 0.0]),
+            size : (4.0, 1.0),
+            acceleration : glm::Vec2::zeros(),
+            rng : Stream::new(seed),
+            owed : 0.0,
+        }
+    }
+
+    /// Starts the random picks over from seed.
+    pub fn reseed(&mut self, seed : u64) {
+        self.rng = Stream::new(seed);
+    }
+
+    fn between(&mut self, range : (f32, f32)) -> f32 {
+        range.0 + (range.1 - range.0) * self.rng.gen::<f32>()
+    }
+
+    fn spawn(&mut self, position : glm::Vec2) -> Particle {
+        let angle = self.direction + self.spread * (self.rng.gen::<f32>() * 2.0 - 1.0);
+        let speed = self.between(self.speed);
+        Particle {
+            position,
+            velocity : glm::vec2(angle.cos(), angle.sin()) * speed,
+            acceleration : self.acceleration,
+            age : 0.0,
+            lifetime : self.between(self.lifetime),
+            colour : self.colour,
+            size : self.size,
+        }
+    }
+
+    pub(super) fn digest(&self, sum : &mut Checksum) {
+        sum.u64(self.rng.state());
+        sum.f32(self.owed);
+    }
+}
+
+/// A particle carries its emitter's curves along, so it outlives the emitter.
+#[derive(Debug, Clone)]
+pub struct Particle {
+    pub position : glm::Vec2,
+    pub velocity : glm::Vec2,
+    pub acceleration : glm::Vec2,
+    pub age : f32,
+    pub lifetime : f32,
+    pub colour : ([f32; 3], [f32; 3]),
+    pub size : (f32, f32),
+}
+
+impl Particle {
+    /// 0 at birth, 1 at death
+    fn life(&self) -> f32 {
+        (self.age / self.lifetime).min(1.0)
+    }
+
+    pub fn current_colour(&self) -> [f32; 3] {
+        let (start, end, t) = (self.colour.0, self.colour.1, self.life());
+        [start[0] + (end[0] - start[0]) * t, start[1] + (end[1] - start[1]) * t, start[2] + (end[2] - start[2]) * t]
+    }
+
+    pub fn current_size(&self) -> f32 {
+        self.size.0 + (self.size.1 - self.size.0) * self.life()
+    }
+}
+
+/// What the renderer gets to know about a particle
+#[derive(Debug, Clone)]
+pub struct ParticleSprite {
+    pub position : glm::Vec2,
+    pub colour : [f32; 3],
+    pub size : f32,
+}
+
+/// Every live particle, kept apart from the entities since there are lots of them and they only
+/// ever fly, fade and die.
+#[derive(Debug, Clone)]
+pub struct ParticlePool {
+    particles : Vec<Particle>,
+    /// Emitters stop spawning while this many particles are alive
+    pub capacity : usize,
+}
+
+impl Default for ParticlePool {
+    fn default() -> Self {
+        Self { particles : Vec::new(), capacity : 20000 }
+    }
+}
+
+impl ParticlePool {
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Particle> {
+        self.particles.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+    }
+
+    /// Ages and moves every particle, and drops the ones that died.
+    fn step(&mut self, dt : f32) {
+        for particle in self.particles.iter_mut() {
+            particle.velocity += particle.acceleration * dt;
+            particle.position += particle.velocity * dt;
+            particle.age += dt;
+        }
+        self.particles.retain(|x| x.age < x.lifetime);
+    }
+
+    pub fn sprites(&self) -> Vec<ParticleSprite> {
+        self.particles.iter()
+            .map(|x| ParticleSprite { position : x.position, colour : x.current_colour(), size : x.current_size() })
+            .collect()
+    }
+}
+
+impl Game {
+    /// Moves the particles already alive, then has every emitter spawn what it's owed this update.
+    pub(super) fn update_particles(&mut self) {
+        let dt = self.physics.time_step;
+        self.particles.step(dt);
+        for entity in self.entities.iter().filter(|x| x.components.contains(CompFlag::Pos | CompFlag::Emt)) {
+            let id = entity.id as usize;
+            let pos = &self.positions[id];
+            let emitter = &mut self.emitters[id];
+            emitter.owed += emitter.rate * dt;
+            while emitter.owed >= 1.0 {
+                emitter.owed -= 1.0;
+                if self.particles.particles.len() >= self.particles.capacity {
+                    continue;
+                }
+                self.particles.particles.push(emitter.spawn(glm::vec2(pos.x, pos.y)));
+            }
+        }
+    }
+}
+
+impl Save for ParticleEmitter {
+    fn save(&self, w : &mut Writer) {
+        (self.rate, self.direction, self.spread).save(w);
+        (self.speed, self.lifetime).save(w);
+        (self.colour, self.size).save(w);
+        self.acceleration.save(w);
+        self.rng.save(w);
+        self.owed.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (rate, direction, spread) = Save::load(r)?;
+        let (speed, lifetime) = Save::load(r)?;
+        let (colour, size) = Save::load(r)?;
+        let acceleration = Save::load(r)?;
+        let rng = Save::load(r)?;
+        let owed = Save::load(r)?;
+        Ok(Self { rate, direction, spread, speed, lifetime, colour, size, acceleration, rng, owed })
+    }
+}
+
+impl Save for Particle {
+    fn save(&self, w : &mut Writer) {
+        (self.position, self.velocity, self.acceleration).save(w);
+        (self.age, self.lifetime).save(w);
+        (self.colour, self.size).save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (position, velocity, acceleration) = Save::load(r)?;
+        let (age, lifetime) = Save::load(r)?;
+        let (colour, size) = Save::load(r)?;
+        Ok(Self { position, velocity, acceleration, age, lifetime, colour, size })
+    }
+}
+
+impl Save for ParticlePool {
+    fn save(&self, w : &mut Writer) {
+        self.particles.save(w);
+        self.capacity.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        Ok(Self { particles : Save::load(r)?, capacity : Save::load(r)? })
+    }
+}
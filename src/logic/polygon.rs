@@ -0,0 +1,178 @@
+
+pub const MAX_POLYGON_VERTICES : usize = 8;
+
+/// Convex polygon in the local space of its entity, wound counter-clockwise.
+#[derive(Debug, Clone)]
+pub struct Polygon {
+    vertices : [glm::Vec2; MAX_POLYGON_VERTICES],
+    count : usize,
+}
+
+impl Polygon {
+    /// Fails unless there are between 3 and MAX_POLYGON_VERTICES vertices forming a convex shape.
+    /// Clockwise input is reversed.
+    pub fn new(vertices : &[glm::Vec2]) -> Result<Polygon, String> {
+        if vertices.len() < 3 || vertices.len() > MAX_POLYGON_VERTICES {
+            return Err(format!("A polygon needs between 3 and {} vertices, got {}", MAX_POLYGON_VERTICES, vertices.len()));
+        }
+        let mut polygon = Polygon {
+            vertices : [glm::Vec2::zeros(); MAX_POLYGON_VERTICES],
+            count : vertices.len(),
+        };
+        polygon.vertices[..vertices.len()].copy_from_slice(vertices);
+        if polygon.signed_area() < 0.0 {
+            polygon.vertices[..vertices.len()].reverse();
+        }
+
+        let n = polygon.count;
+        for i in 0..n {
+            let (a, b, c) = (polygon.vertices[i], polygon.vertices[(i + 1) % n], polygon.vertices[(i + 2) % n]);
+            if cross(&(b - a), &(c - b)) <= 0.0 {
+                return Err(String::from("Polygon is not convex, or has repeated or collinear vertices"));
+            }
+        }
+        Ok(polygon)
+    }
+
+    /// Axis aligned box with the given half extents, as a polygon.
+    pub fn rectangle(half_width : f32, half_height : f32) -> Polygon {
+        let mut vertices = [glm::Vec2::zeros(); MAX_POLYGON_VERTICES];
+        vertices[..4].copy_from_slice(&[
+            glm::vec2(-half_width, -half_height),
+            glm::vec2(half_width, -half_height),
+            glm::vec2(half_width, half_height),
+            glm::vec2(-half_width, half_height),
+        ]);
+        Polygon { vertices, count : 4 }
+    }
+
+    pub fn vertices(&self) -> &[glm::Vec2] {
+        &self.vertices[..self.count]
+    }
+
+    fn signed_area(&self) -> f32 {
+        let v = self.vertices();
+        (0..v.len()).map(|i| cross(&v[i], &v[(i + 1) % v.len()])).sum::<f32>() / 2.0
+    }
+
+    /// Distance from the local origin to the furthest vertex.
+    pub fn bounding_radius(&self) -> f32 {
+        self.vertices().iter().map(|v| v.norm()).fold(0.0, f32::max)
+    }
+
+    /// Moment of inertia around the local origin for a polygon of uniform density.
+    pub fn moment_of_inertia(&self, mass : f32) -> f32 {
+        let v = self.vertices();
+        let mut numerator = 0.0;
+        let mut denominator = 0.0;
+        for i in 0..v.len() {
+            let (a, b) = (v[i], v[(i + 1) % v.len()]);
+            let c = cross(&a, &b);
+            numerator += c * (a.dot(&a) + a.dot(&b) + b.dot(&b));
+            denominator += c;
+        }
+        mass * numerator / (6.0 * denominator)
+    }
+
+    /// The vertices moved into world space, rotated by angle around position.
+    pub fn transformed(&self, position : &glm::Vec2, angle : f32) -> WorldPolygon {
+        let (sin, cos) = angle.sin_cos();
+        let mut vertices = [glm::Vec2::zeros(); MAX_POLYGON_VERTICES];
+        for (world, local) in vertices.iter_mut().zip(self.vertices()) {
+            *world = position + glm::vec2(local.x * cos - local.y * sin, local.x * sin + local.y * cos);
+        }
+        WorldPolygon { vertices, count : self.count }
+    }
+}
+
+/// A polygon placed in the world. Only lives for the duration of a test.
+pub struct WorldPolygon {
+    vertices : [glm::Vec2; MAX_POLYGON_VERTICES],
+    count : usize,
+}
+
+impl WorldPolygon {
+    pub fn vertices(&self) -> &[glm::Vec2] {
+        &self.vertices[..self.count]
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn vertex(&self, i : usize) -> glm::Vec2 {
+        self.vertices[i % self.count]
+    }
+
+    /// Outward unit normal of the edge from vertex i to vertex i + 1.
+    pub fn normal(&self, i : usize) -> glm::Vec2 {
+        let edge = self.vertex(i + 1) - self.vertex(i);
+        glm::vec2(edge.y, -edge.x).normalize()
+    }
+
+    /// The edge whose outward normal points furthest along the given direction, with how far the
+    /// point lies outside of it. Positive means the point is outside the polygon.
+    pub fn max_separation(&self, point : &glm::Vec2) -> (usize, f32) {
+        (0..self.count)
+            .map(|i| (i, self.normal(i).dot(&(point - self.vertex(i)))))
+            .fold((0, f32::NEG_INFINITY), |best, x| if x.1 > best.1 { x } else { best })
+    }
+
+    /// Distance and normal of the first point where a ray hits the polygon grown by radius in every direction.
+    pub fn ray(&self, o : &glm::Vec2, dir : &glm::Vec2, radius : f32) -> Option<(f32, glm::Vec2)> {
+        let (face, separation) = self.max_separation(o);
+        if separation <= 0.0 {
+            return Some((0.0, self.normal(face)));
+        }
+        if radius > 0.0 && self.distance(o) <= radius {
+            return Some((0.0, -dir));
+        }
+
+        let mut best : Option<(f32, glm::Vec2)> = None;
+        let mut consider = |hit : Option<(f32, glm::Vec2)>| {
+            if let Some(hit) = hit {
+                if best.is_none_or(|b| hit.0 < b.0) {
+                    best = Some(hit);
+                }
+            }
+        };
+        for i in 0..self.count {
+            let n = self.normal(i);
+            let facing = dir.dot(&n);
+            if facing >= 0.0 {
+                continue;
+            }
+            // The edge pushed out by radius, which a ray can only enter from the outside
+            let a = self.vertex(i) + n * radius;
+            let b = self.vertex(i + 1) + n * radius;
+            let t = n.dot(&(a - o)) / facing;
+            if t < 0.0 {
+                continue;
+            }
+            let p = o + dir * t;
+            let along = (p - a).dot(&(b - a));
+            if along >= 0.0 && along <= (b - a).norm_squared() {
+                consider(Some((t, n)));
+            }
+        }
+        if radius > 0.0 {
+            for v in self.vertices() {
+                consider(super::raycast::ray_circle(o, dir, v, radius));
+            }
+        }
+        best
+    }
+
+    /// Distance from a point outside the polygon to its closest edge.
+    fn distance(&self, point : &glm::Vec2) -> f32 {
+        (0..self.count).map(|i| {
+            let (a, b) = (self.vertex(i), self.vertex(i + 1));
+            let t = ((point - a).dot(&(b - a)) / (b - a).norm_squared()).clamp(0.0, 1.0);
+            (point - (a + (b - a) * t)).norm()
+        }).fold(f32::INFINITY, f32::min)
+    }
+}
+
+pub fn cross(a : &glm::Vec2, b : &glm::Vec2) -> f32 {
+    a.x * b.y - a.y * b.x
+}
@@ -0,0 +1,107 @@
+
+use super::{Asset, AngularVelocity, BoundaryPolicy, Collider, CompFlag, EntityId, Game, Lifetime, Mass, Orientation, ParticleEmitter, Position, Sensor, StateMachine, Steering, Tweening, Velocity};
+
+/// Index of a prefab in Game::prefabs
+pub type PrefabId = usize;
+
+/// A blueprint entities are spawned from. Position is left out, it's given at spawn time.
+#[derive(Clone)]
+pub struct Prefab {
+    pub components : CompFlag,
+    pub velocity : Velocity,
+    pub orientation : Orientation,
+    pub angular_velocity : AngularVelocity,
+    pub asset : Asset,
+    pub collider : Collider,
+    pub sensor : Sensor,
+    pub boundary_policy : BoundaryPolicy,
+    pub mass : Mass,
+    pub steering : Steering,
+    pub emitter : ParticleEmitter,
+    pub lifetime : Lifetime,
+    pub tweening : Tweening,
+    pub state_machine : StateMachine,
+}
+
+impl Default for Prefab {
+    fn default() -> Self {
+        Self {
+            components : CompFlag::empty(),
+            velocity : Velocity::default(),
+            orientation : Orientation::default(),
+            angular_velocity : AngularVelocity::default(),
+            asset : Asset::default(),
+            collider : Collider::default(),
+            sensor : Sensor::default(),
+            boundary_policy : BoundaryPolicy::default(),
+            mass : Mass::default(),
+            steering : Steering::default(),
+            emitter : ParticleEmitter::default(),
+            lifetime : Lifetime::default(),
+            tweening : Tweening::default(),
+            state_machine : StateMachine::default(),
+        }
+    }
+}
+
+impl Prefab {
+    pub fn new(components : CompFlag) -> Self {
+        Self { components, ..Self::default() }
+    }
+
+    /// A prefab copying what the entity is made of right now. Sleep is left out, spawned entities
+    /// start awake, and state machines start over from their first state.
+    pub fn from_entity(game : &Game, id : EntityId) -> Self {
+        let i = id as usize;
+        Self {
+            components : game.entities[i].components - CompFlag::Slp,
+            velocity : game.velocities[i].clone(),
+            orientation : game.orientations[i].clone(),
+            angular_velocity : game.angular_velocities[i].clone(),
+            asset : game.assets[i].clone(),
+            collider : game.colliders[i].clone(),
+            sensor : game.sensors[i].clone(),
+            boundary_policy : game.boundary_policies[i],
+            mass : game.masses[i].clone(),
+            steering : game.steerings[i].clone(),
+            emitter : game.emitters[i].clone(),
+            lifetime : game.lifetimes[i].clone(),
+            tweening : game.tweenings[i].clone(),
+            state_machine : StateMachine::new(game.state_machines[i].machine),
+        }
+    }
+}
+
+impl Game {
+    pub fn add_prefab(&mut self, prefab : Prefab) -> PrefabId {
+        self.prefabs.push(prefab);
+        self.prefabs.len() - 1
+    }
+
+    /// Spawns a copy of the prefab at position. Panics if there's no such prefab. Every copy's
+    /// emitter gets a seed of its own, so copies don't all give off the same particles.
+    pub fn spawn_prefab(&mut self, prefab_id : PrefabId, position : &Position) -> EntityId {
+        let id = self.add_entity(self.prefabs[prefab_id].components);
+        let i = id as usize;
+        let prefab = &self.prefabs[prefab_id];
+        self.positions[i] = position.clone();
+        self.velocities[i] = prefab.velocity.clone();
+        self.orientations[i] = prefab.orientation.clone();
+        self.angular_velocities[i] = prefab.angular_velocity.clone();
+        self.assets[i] = prefab.asset.clone();
+        self.colliders[i] = prefab.collider.clone();
+        self.sensors[i] = prefab.sensor.clone();
+        self.boundary_policies[i] = prefab.boundary_policy;
+        self.masses[i] = prefab.mass.clone();
+        self.steerings[i] = prefab.steering.clone();
+        self.emitters[i] = prefab.emitter.clone();
+        self.lifetimes[i] = prefab.lifetime.clone();
+        self.tweenings[i] = prefab.tweening.clone();
+        self.state_machines[i] = prefab.state_machine.clone();
+        let emitter_seed = self.random.next_seed(&format!("prefab {} emitter", prefab_id));
+        self.emitters[i].reseed(emitter_seed);
+        let steering_seed = self.random.next_seed(&format!("prefab {} steering", prefab_id));
+        self.steerings[i].reseed(steering_seed);
+        id
+    }
+}
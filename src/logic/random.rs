@@ -0,0 +1,141 @@
+
+use std::collections::BTreeMap;
+
+use rand::RngCore;
+
+use super::{Checksum, EntityId};
+use super::snapshot::{Reader, Save, Writer};
+
+const GOLDEN_GAMMA : u64 = 0x9e37_79b9_7f4a_7c15;
+
+/// One SplitMix64 step
+fn splitmix(state : &mut u64) -> u64 {
+    *state = state.wrapping_add(GOLDEN_GAMMA);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Seed of a stream derived from a parent seed, well apart from the parent's other children
+fn derive(seed : u64, child : u64) -> u64 {
+    let mut state = seed ^ child.wrapping_mul(GOLDEN_GAMMA);
+    splitmix(&mut state)
+}
+
+/// FNV-1a, spelled out so stream names map to the same seeds with every compiler and platform
+fn hash_key(key : &str) -> u64 {
+    key.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3))
+}
+
+/// A SplitMix64 generator. Its whole state is one number, so it's cheap to copy and save.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stream {
+    state : u64,
+}
+
+impl Stream {
+    pub fn new(seed : u64) -> Self {
+        Self { state : seed }
+    }
+
+    pub fn state(&self) -> u64 {
+        self.state
+    }
+}
+
+impl Save for Stream {
+    fn save(&self, w : &mut Writer) {
+        self.state.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        Ok(Stream::new(Save::load(r)?))
+    }
+}
+
+impl RngCore for Stream {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        splitmix(&mut self.state)
+    }
+
+    fn fill_bytes(&mut self, dest : &mut [u8]) {
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest : &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Where every random number in the world comes from. Each system or entity gets a stream of its
+/// own, derived from the world seed and a name, so the same seed replays the same world and a new
+/// consumer never shifts the numbers anybody else gets.
+#[derive(Debug, Clone, Default)]
+pub struct Randomness {
+    seed : u64,
+    /// How many seeds next_seed handed out for each name, by the name's hash
+    counters : BTreeMap<u64, u64>,
+}
+
+impl Randomness {
+    pub fn new(seed : u64) -> Self {
+        Self { seed, counters : BTreeMap::new() }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Seed of the stream called key. Only depends on the world seed and the key.
+    pub fn seed_for(&self, key : &str) -> u64 {
+        derive(self.seed, hash_key(key))
+    }
+
+    pub fn stream(&self, key : &str) -> Stream {
+        Stream::new(self.seed_for(key))
+    }
+
+    /// A stream for one entity of a system.
+    pub fn entity_stream(&self, key : &str, id : EntityId) -> Stream {
+        Stream::new(derive(self.seed_for(key), id as u64))
+    }
+
+    /// A different seed on every call with the same key, for things made over and over, like
+    /// copies of a prefab. The nth call for a key always gives the same seed.
+    pub fn next_seed(&mut self, key : &str) -> u64 {
+        let hash = hash_key(key);
+        let counter = self.counters.entry(hash).or_insert(0);
+        *counter += 1;
+        derive(derive(self.seed, hash), *counter)
+    }
+
+    pub(super) fn digest(&self, sum : &mut Checksum) {
+        sum.u64(self.seed);
+        for (hash, counter) in &self.counters {
+            sum.u64(*hash);
+            sum.u64(*counter);
+        }
+    }
+}
+
+impl Save for Randomness {
+    fn save(&self, w : &mut Writer) {
+        self.seed.save(w);
+        self.counters.iter().map(|(hash, counter)| (*hash, *counter)).collect::<Vec<_>>().save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let seed = Save::load(r)?;
+        let counters : Vec<(u64, u64)> = Save::load(r)?;
+        Ok(Self { seed, counters : counters.into_iter().collect() })
+    }
+}
@@ -0,0 +1,326 @@
+
+use std::collections::HashSet;
+
+use super::{CompFlag, EntityId, Game, Position, Shape};
+
+/// Restricts which entities a ray or shape cast can hit.
+#[derive(Debug, Clone)]
+pub struct QueryFilter {
+    /// Components an entity needs on top of a position and a collider to be hit.
+    pub components : CompFlag,
+    /// Components that keep an entity from being hit, like Sen to see through sensors.
+    pub excluded : CompFlag,
+    /// Only colliders sharing at least one layer with this mask are hit.
+    pub layers : u32,
+    /// An entity the query never hits, usually the one doing the casting.
+    pub exclude : Option<EntityId>,
+}
+
+impl Default for QueryFilter {
+    fn default() -> Self {
+        Self {
+            components : CompFlag::empty(),
+            excluded : CompFlag::empty(),
+            layers : u32::MAX,
+            exclude : None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RayHit {
+    pub entity : EntityId,
+    /// How far along the ray the hit happened. Zero if the cast started inside the collider.
+    pub distance : f32,
+    /// The point on the ray at the time of the hit. For shape casts this is the centre of the cast circle.
+    pub point : Position,
+    /// Unit normal of the hit surface, pointing back out of the collider.
+    pub normal : glm::Vec2,
+}
+
+impl Game {
+    /// Casts a ray and returns the closest hit collider, if any.
+    pub fn raycast(&self, origin : &Position, direction : glm::Vec2, max_distance : f32, filter : &QueryFilter) -> Option<RayHit> {
+        self.cast(origin, 0.0, direction, max_distance, filter, true).pop()
+    }
+
+    /// Casts a ray and returns every collider it passes through, closest first.
+    pub fn raycast_all(&self, origin : &Position, direction : glm::Vec2, max_distance : f32, filter : &QueryFilter) -> Vec<RayHit> {
+        self.cast(origin, 0.0, direction, max_distance, filter, false)
+    }
+
+    /// Sweeps a circle of the given radius and returns the first collider it touches, if any.
+    pub fn shape_cast(&self, origin : &Position, radius : f32, direction : glm::Vec2, max_distance : f32, filter : &QueryFilter) -> Option<RayHit> {
+        self.cast(origin, radius, direction, max_distance, filter, true).pop()
+    }
+
+    /// Sweeps a circle of the given radius and returns every collider it touches, closest first.
+    pub fn shape_cast_all(&self, origin : &Position, radius : f32, direction : glm::Vec2, max_distance : f32, filter : &QueryFilter) -> Vec<RayHit> {
+        self.cast(origin, radius, direction, max_distance, filter, false)
+    }
+
+    fn passes_filter(&self, id : EntityId, filter : &QueryFilter) -> bool {
+        let entity = &self.entities[id as usize];
+        filter.exclude != Some(id)
+            && entity.components.contains(CompFlag::Pos | CompFlag::Col | filter.components)
+            && !entity.components.intersects(filter.excluded)
+            && self.colliders[id as usize].layers & filter.layers != 0
+    }
+
+    /// Walks the grid cells along the ray with a DDA traversal, testing the colliders sorted into
+    /// each cell and its neighbours.
+    /// Colliders are assumed to fit within a grid cell of their centre, since that's where entities are sorted.
+    /// max_distance has to be finite.
+    fn cast(&self, origin : &Position, radius : f32, direction : glm::Vec2, max_distance : f32, filter : &QueryFilter, first_only : bool) -> Vec<RayHit> {
+        debug_assert!(max_distance.is_finite(), "Casts need a finite max distance to terminate!");
+        let mut hits : Vec<RayHit> = Vec::new();
+        if direction.norm_squared() == 0.0 {
+            return hits;
+        }
+        let dir = direction.normalize();
+        let o = glm::vec2(origin.x, origin.y);
+
+        let grid = &self.spacially_sorted;
+        let size = grid.cell_size();
+        let reach = 1 + (radius / size).ceil() as i32;
+        let mut cell = grid.get_location(origin);
+
+        // Distance along the ray to the next cell boundary on each axis, and between boundaries
+        let step = (dir.x.signum() as i32, dir.y.signum() as i32);
+        let boundary = |c : i32, o : f32, d : f32| {
+            if d > 0.0 {
+                ((c + 1) as f32 * size - o) / d
+            } else if d < 0.0 {
+                (c as f32 * size - o) / d
+            } else {
+                f32::INFINITY
+            }
+        };
+        let mut t_next = (boundary(cell.0, o.x, dir.x), boundary(cell.1, o.y, dir.y));
+        let t_delta = (size / dir.x.abs(), size / dir.y.abs());
+
+        let mut tested : HashSet<EntityId> = HashSet::new();
+        let mut t_cell = 0.0;
+        while t_cell <= max_distance {
+            if first_only && hits.first().is_some_and(|h| h.distance < t_cell) {
+                break;
+            }
+            for cx in (cell.0 - reach)..=(cell.0 + reach) {
+                for cy in (cell.1 - reach)..=(cell.1 + reach) {
+                    // Static colliders live in a grid of their own, with the same cells
+                    for grid in [grid, &self.static_sorted] {
+                        let nearby = match grid.find_in_cell(&(cx, cy)) {
+                            Some(n) => n,
+                            None => continue,
+                        };
+                        for &id in nearby.iter() {
+                            if !tested.insert(id) || !self.passes_filter(id, filter) {
+                                continue;
+                            }
+                            let centre = &self.positions[id as usize];
+                            let c = glm::vec2(centre.x, centre.y);
+                            let hit = match &self.colliders[id as usize].shape {
+                                Shape::Circle { radius : r } => ray_circle(&o, &dir, &c, r + radius),
+                                Shape::Aabb { half_width, half_height } => ray_rounded_box(&o, &dir, &c, *half_width, *half_height, radius),
+                                Shape::Polygon(polygon) => polygon.transformed(&c, self.angle(id)).ray(&o, &dir, radius),
+                            };
+                            if let Some((distance, normal)) = hit {
+                                if distance > max_distance {
+                                    continue;
+                                }
+                                let point = o + dir * distance;
+                                let hit = RayHit { entity : id, distance, point : Position { x : point.x, y : point.y }, normal };
+                                if !first_only {
+                                    hits.push(hit);
+                                } else if hits.first().is_none_or(|h| distance < h.distance) {
+                                    hits.clear();
+                                    hits.push(hit);
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            if t_next.0 < t_next.1 {
+                t_cell = t_next.0;
+                t_next.0 += t_delta.0;
+                cell.0 += step.0;
+            } else {
+                t_cell = t_next.1;
+                t_next.1 += t_delta.1;
+                cell.1 += step.1;
+            }
+        }
+
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        hits
+    }
+}
+
+/// Distance and normal of the first intersection between a ray with unit direction dir and a circle.
+/// Rays starting inside the circle hit at distance zero.
+pub(super) fn ray_circle(o : &glm::Vec2, dir : &glm::Vec2, c : &glm::Vec2, r : f32) -> Option<(f32, glm::Vec2)> {
+    let m = o - c;
+    let b = m.dot(dir);
+    let cc = m.norm_squared() - r * r;
+    if cc <= 0.0 {
+        return Some((0.0, inside_normal(&m, dir)));
+    }
+    if b > 0.0 {
+        return None;
+    }
+    let disc = b * b - cc;
+    if disc < 0.0 {
+        return None;
+    }
+    let t = -b - disc.sqrt();
+    let n = m + dir * t;
+    Some((t, if r > 0.0 { n / r } else { -dir }))
+}
+
+/// Slab test against an axis aligned box with the given half extents.
+pub(super) fn ray_box(o : &glm::Vec2, dir : &glm::Vec2, c : &glm::Vec2, hw : f32, hh : f32) -> Option<(f32, glm::Vec2)> {
+    let mut t_enter = f32::NEG_INFINITY;
+    let mut t_exit = f32::INFINITY;
+    let mut normal = -dir;
+    for (axis, h) in [(0, hw), (1, hh)].iter() {
+        let (o, d, c) = (o[*axis], dir[*axis], c[*axis]);
+        if d == 0.0 {
+            if o < c - h || o > c + h {
+                return None;
+            }
+            continue;
+        }
+        let mut t1 = (c - h - o) / d;
+        let mut t2 = (c + h - o) / d;
+        if t1 > t2 {
+            std::mem::swap(&mut t1, &mut t2);
+        }
+        if t1 > t_enter {
+            t_enter = t1;
+            normal = glm::Vec2::zeros();
+            normal[*axis] = -d.signum();
+        }
+        t_exit = t_exit.min(t2);
+        if t_enter > t_exit {
+            return None;
+        }
+    }
+    if t_exit < 0.0 {
+        None
+    } else if t_enter < 0.0 {
+        Some((0.0, inside_normal(&(o - c), dir)))
+    } else {
+        Some((t_enter, normal))
+    }
+}
+
+/// A box grown by radius in every direction with rounded corners, which is what a swept circle sees.
+/// It's the union of the box grown along each axis and a circle at each corner.
+fn ray_rounded_box(o : &glm::Vec2, dir : &glm::Vec2, c : &glm::Vec2, hw : f32, hh : f32, radius : f32) -> Option<(f32, glm::Vec2)> {
+    if radius <= 0.0 {
+        return ray_box(o, dir, c, hw, hh);
+    }
+    let corners = [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)];
+    let mut best = ray_box(o, dir, c, hw + radius, hh);
+    let candidates = std::iter::once(ray_box(o, dir, c, hw, hh + radius))
+        .chain(corners.iter().map(|(x, y)| ray_circle(o, dir, &(c + glm::vec2(x * hw, y * hh)), radius)));
+    for candidate in candidates.flatten() {
+        if best.as_ref().is_none_or(|b| candidate.0 < b.0) {
+            best = Some(candidate);
+        }
+    }
+    best
+}
+
+fn inside_normal(offset : &glm::Vec2, dir : &glm::Vec2) -> glm::Vec2 {
+    if offset.norm_squared() > 0.0 {
+        offset.normalize()
+    } else {
+        -dir
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A static collider of the given shape, sorted into the grid by the update
+    fn obstacle(game : &mut Game, x : f32, y : f32, shape : Shape) -> EntityId {
+        let id = game.add_entity(CompFlag::Pos | CompFlag::Col | CompFlag::Sta);
+        game.positions[id as usize] = Position { x, y };
+        game.colliders[id as usize].shape = shape;
+        id
+    }
+
+    fn sorted(mut game : Game) -> Game {
+        let (mut tx, _rx) = std::sync::mpsc::sync_channel(1);
+        game.update(&mut tx);
+        game
+    }
+
+    const ORIGIN : Position = Position { x : 0.0, y : 0.0 };
+
+    #[test]
+    fn rays_hit_circles_and_boxes_on_their_near_side() {
+        let mut game = Game::new();
+        let circle = obstacle(&mut game, 0.5, 0.0, Shape::Circle { radius : 0.1 });
+        let aabb = obstacle(&mut game, 0.0, 0.5, Shape::Aabb { half_width : 0.1, half_height : 0.2 });
+        let game = sorted(game);
+
+        let hit = game.raycast(&ORIGIN, glm::vec2(2.0, 0.0), 1.0, &QueryFilter::default()).unwrap();
+        assert_eq!(hit.entity, circle);
+        assert!((hit.distance - 0.4).abs() < 1e-5, "distance {}", hit.distance);
+        assert!((hit.normal - glm::vec2(-1.0, 0.0)).norm() < 1e-5, "normal {:?}", hit.normal);
+
+        let hit = game.raycast(&ORIGIN, glm::vec2(0.0, 1.0), 1.0, &QueryFilter::default()).unwrap();
+        assert_eq!(hit.entity, aabb);
+        assert!((hit.distance - 0.3).abs() < 1e-5, "distance {}", hit.distance);
+        assert!((hit.normal - glm::vec2(0.0, -1.0)).norm() < 1e-5, "normal {:?}", hit.normal);
+
+        assert!(game.raycast(&ORIGIN, glm::vec2(1.0, 0.0), 0.35, &QueryFilter::default()).is_none());
+        assert!(game.raycast(&ORIGIN, glm::vec2(-1.0, 0.0), 1.0, &QueryFilter::default()).is_none());
+        assert!(game.raycast(&ORIGIN, glm::vec2(0.0, 0.0), 1.0, &QueryFilter::default()).is_none());
+    }
+
+    #[test]
+    fn filters_let_rays_through_what_they_exclude() {
+        let mut game = Game::new();
+        let sensor = obstacle(&mut game, 0.2, 0.0, Shape::Circle { radius : 0.05 });
+        game.entities[sensor as usize].components |= CompFlag::Sen;
+        let near = obstacle(&mut game, 0.4, 0.0, Shape::Circle { radius : 0.05 });
+        let far = obstacle(&mut game, 0.6, 0.0, Shape::Circle { radius : 0.05 });
+        game.colliders[near as usize].layers = 0b01;
+        game.colliders[far as usize].layers = 0b10;
+        let game = sorted(game);
+        let right = glm::vec2(1.0, 0.0);
+
+        let hit = |filter : QueryFilter| game.raycast(&ORIGIN, right, 1.0, &filter).map(|x| x.entity);
+        assert_eq!(hit(QueryFilter::default()), Some(sensor));
+        assert_eq!(hit(QueryFilter { excluded : CompFlag::Sen, ..QueryFilter::default() }), Some(near));
+        assert_eq!(hit(QueryFilter { excluded : CompFlag::Sen, exclude : Some(near), ..QueryFilter::default() }), Some(far));
+        assert_eq!(hit(QueryFilter { excluded : CompFlag::Sen, layers : 0b10, ..QueryFilter::default() }), Some(far));
+        assert_eq!(hit(QueryFilter { components : CompFlag::Vel, ..QueryFilter::default() }), None);
+    }
+
+    #[test]
+    fn rays_find_everything_along_them_across_many_cells() {
+        let mut game = Game::new();
+        game.set_cell_size(0.1);
+        // Along the diagonal, so the walk steps through cells on both axes
+        let along : Vec<EntityId> = [0.25, 0.75, 1.35]
+            .iter()
+            .map(|d| obstacle(&mut game, d * std::f32::consts::FRAC_1_SQRT_2, d * std::f32::consts::FRAC_1_SQRT_2, Shape::Circle { radius : 0.02 }))
+            .collect();
+        obstacle(&mut game, 0.5, 0.0, Shape::Circle { radius : 0.02 });
+        let game = sorted(game);
+
+        let hits = game.raycast_all(&ORIGIN, glm::vec2(1.0, 1.0), 2.0, &QueryFilter::default());
+        assert_eq!(hits.iter().map(|x| x.entity).collect::<Vec<_>>(), along);
+        for (hit, d) in hits.iter().zip([0.25, 0.75, 1.35]) {
+            assert!((hit.distance - (d - 0.02)).abs() < 1e-4, "distance {} for {}", hit.distance, d);
+        }
+        assert_eq!(game.raycast(&ORIGIN, glm::vec2(1.0, 1.0), 2.0, &QueryFilter::default()).map(|x| x.entity), Some(along[0]));
+    }
+}
@@ -0,0 +1,99 @@
+
+use super::{collision, CompFlag, EntityId, Game};
+
+/// Overlap-only volume. Needs a collider for its shape, but never pushes anything.
+#[derive(Default, Debug, Clone)]
+pub struct Sensor {
+    /// Entities overlapping the sensor as of the last tick, sorted by id
+    pub inside : Vec<EntityId>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SensorEventKind {
+    Enter,
+    Stay,
+    Exit,
+}
+
+#[derive(Debug, Clone)]
+pub struct SensorEvent {
+    pub sensor : EntityId,
+    pub other : EntityId,
+    pub kind : SensorEventKind,
+}
+
+impl Game {
+    /// Finds what every sensor overlaps among the candidate pairs, and replaces last tick's sensor
+    /// events with the enters, stays and exits since then.
+    pub(super) fn update_sensors(&mut self, pairs : &[(EntityId, EntityId)]) {
+        let is_sensor = |id : EntityId| self.entities[id as usize].components.contains(CompFlag::Sen);
+
+        let mut now_inside : Vec<Vec<EntityId>> = vec![Vec::new(); self.sensors.len()];
+        for &(a, b) in pairs {
+            if !is_sensor(a) && !is_sensor(b) {
+                continue;
+            }
+            let (ca, cb) = (&self.colliders[a as usize], &self.colliders[b as usize]);
+            if ca.layers & cb.layers == 0 {
+                continue;
+            }
+            if collision::contact(&self.positions[a as usize], self.angle(a), &ca.shape, &self.positions[b as usize], self.angle(b), &cb.shape).is_none() {
+                continue;
+            }
+            if is_sensor(a) {
+                now_inside[a as usize].push(b);
+            }
+            if is_sensor(b) {
+                now_inside[b as usize].push(a);
+            }
+        }
+
+        self.sensor_events.clear();
+        for (sensor, inside) in now_inside.iter_mut().enumerate() {
+            inside.sort_unstable();
+            let before = &self.sensors[sensor].inside;
+            let sensor = sensor as EntityId;
+            for &other in before.iter() {
+                let kind = if inside.binary_search(&other).is_ok() { SensorEventKind::Stay } else { SensorEventKind::Exit };
+                self.sensor_events.push(SensorEvent { sensor, other, kind });
+            }
+            for &other in inside.iter() {
+                if before.binary_search(&other).is_err() {
+                    self.sensor_events.push(SensorEvent { sensor, other, kind : SensorEventKind::Enter });
+                }
+            }
+        }
+        for (sensor, inside) in self.sensors.iter_mut().zip(now_inside) {
+            sensor.inside = inside;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Position, Shape};
+    use super::*;
+
+    #[test]
+    fn despawned_entities_leave_the_sensors_they_were_in() {
+        let mut game = Game::new();
+        let other = game.add_entity(CompFlag::Pos | CompFlag::Vel | CompFlag::Col);
+        game.colliders[other as usize].shape = Shape::Circle { radius : 0.05 };
+        let sensor = game.add_entity(CompFlag::Pos | CompFlag::Col | CompFlag::Sen);
+        game.colliders[sensor as usize].shape = Shape::Circle { radius : 0.1 };
+
+        let (mut tx, _rx) = std::sync::mpsc::sync_channel(1);
+        game.update(&mut tx);
+        assert_eq!(game.sensors[sensor as usize].inside, vec![other]);
+
+        game.despawn(other);
+        assert!(game.sensors[sensor as usize].inside.is_empty());
+        // Whatever reuses the id far away isn't inside either, and never was
+        let reused = game.add_entity(CompFlag::Pos | CompFlag::Vel | CompFlag::Col);
+        assert_eq!(reused, other);
+        game.positions[reused as usize] = Position { x : 0.5, y : 0.0 };
+        game.update(&mut tx);
+        assert!(game.sensors[sensor as usize].inside.is_empty());
+        assert!(game.sensor_events.iter().all(|e| e.other != reused), "{:?}", game.sensor_events);
+    }
+}
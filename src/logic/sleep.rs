@@ -0,0 +1,108 @@
+
+use super::{CompFlag, EntityId, Game, Position, Velocity};
+
+/// Where an entity came to rest, and for how many seconds it has stayed around there.
+#[derive(Default, Debug, Clone)]
+pub struct Rest {
+    pub anchor : Position,
+    pub time : f32,
+}
+
+impl Game {
+    /// Wakes a sleeping entity, so it's integrated and collides again from the next update on.
+    /// Needed after changing its velocity or position from outside the game.
+    pub fn wake(&mut self, id : EntityId) {
+        self.entities[id as usize].components.remove(CompFlag::Slp);
+        self.rests[id as usize].time = 0.0;
+    }
+
+    fn is_asleep(&self, id : EntityId) -> bool {
+        self.entities[id as usize].components.contains(CompFlag::Slp)
+    }
+
+    /// Wakes sleepers that something other than a contact is about to move: a force, or a
+    /// constraint linking them to an awake entity.
+    pub(super) fn wake_disturbed(&mut self) {
+        let pushed : Vec<EntityId> = self.entities.iter()
+            .filter(|x| x.components.contains(CompFlag::Slp) && x.components.intersects(CompFlag::Frc | CompFlag::Mas))
+            .filter(|x| {
+                let force = &self.forces[x.id as usize];
+                force.x != 0.0 || force.y != 0.0 || force.torque != 0.0
+            })
+            .map(|x| x.id)
+            .collect();
+        for id in pushed {
+            self.wake(id);
+        }
+
+        let linked : Vec<EntityId> = self.constraints.iter()
+            .filter(|c| self.is_asleep(c.a) != self.is_asleep(c.b))
+            .map(|c| if self.is_asleep(c.a) { c.a } else { c.b })
+            .collect();
+        for id in linked {
+            self.wake(id);
+        }
+    }
+
+    /// Wakes whichever of the two touching entities is asleep, unless the awake one is itself
+    /// settling down. Otherwise resting neighbours would keep waking each other.
+    pub(super) fn wake_on_contact(&mut self, a : EntityId, b : EntityId) {
+        for (sleeper, other) in [(a, b), (b, a)] {
+            if self.is_asleep(sleeper) && !self.is_asleep(other) && self.rests[other as usize].time == 0.0 {
+                self.wake(sleeper);
+            }
+        }
+    }
+
+    /// Counts how long every moving collider has been resting, and puts to sleep the ones that have
+    /// been for time_to_sleep. Resting means its average speed since it came to rest is below the
+    /// sleep velocity, rather than its current speed, since bodies pressed against each other keep
+    /// some velocity that the position correction cancels out every update.
+    pub(super) fn update_sleep(&mut self) {
+        let dt = self.physics.time_step;
+        let threshold = self.physics.sleep_velocity;
+        // Only contacts wake sleepers up again, so bodies without a collider never sleep. Neither do
+        // fluid particles, pushed around by pressure, or steered ones, moved by their behaviours.
+        for entity in self.entities.iter_mut().filter(|x| x.components.contains(CompFlag::Pos | CompFlag::Vel | CompFlag::Col) && !x.components.intersects(CompFlag::Sta | CompFlag::Slp | CompFlag::Fld | CompFlag::Str)) {
+            let id = entity.id as usize;
+            let pos = &self.positions[id];
+            let rest = &mut self.rests[id];
+            let moved = (pos.x - rest.anchor.x).hypot(pos.y - rest.anchor.y);
+            let spin = if entity.components.contains(CompFlag::Ang) { self.angular_velocities[id].w.abs() } else { 0.0 };
+            if moved >= threshold * (rest.time + dt) || spin >= threshold {
+                rest.anchor = pos.clone();
+                rest.time = 0.0;
+                continue;
+            }
+            rest.time += dt;
+            if rest.time >= self.physics.time_to_sleep {
+                entity.components.insert(CompFlag::Slp);
+                self.velocities[id] = Velocity::default();
+                self.angular_velocities[id].w = 0.0;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bodies_without_colliders_keep_falling_however_slowly() {
+        let mut game = Game::new();
+        // Slow enough to count as resting for longer than time_to_sleep
+        game.physics.gravity = glm::vec2(0.0, -0.01);
+        let point = game.add_entity(CompFlag::Pos | CompFlag::Vel);
+        let body = game.add_entity(CompFlag::Pos | CompFlag::Vel | CompFlag::Col);
+        game.positions[body as usize] = Position { x : 0.5, y : 0.0 };
+
+        let (mut tx, _rx) = std::sync::mpsc::sync_channel(1);
+        for _ in 0..120 {
+            game.update(&mut tx);
+        }
+        assert!(!game.is_asleep(point));
+        assert!(game.velocities[point as usize].y < -0.015, "Stopped at {}", game.velocities[point as usize].y);
+        assert!(game.is_asleep(body), "A resting collider should still fall asleep");
+    }
+}
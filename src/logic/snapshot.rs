@@ -0,0 +1,1326 @@
+
+use std::{collections::VecDeque, convert::TryFrom};
+
+use super::{
+    Asset, AngularVelocity, BoundaryPolicy, ChecksumLog, Collider, CompFlag, Constraint, Ease, Entity, EntityId,
+    Force, Game, Lifetime, Mass, Orientation, Polygon, Position, Prefab, Property, Repeat, Rest, Sensor, SensorEvent,
+    Shape, Tween, TweenEvent, Tweening, Velocity, Wave, WorldBounds, Attraction, Flocking, Fluid, PhysicsConfig,
+    Schedule, SpawnArea, Behaviour, Target, Tileset, SweepAxis, Broadphase, Randomness, Stream, ParticleEmitter,
+    ParticlePool, StateMachine, Spawner, Tilemap, Navigation,
+};
+use super::grid::EntityGrid;
+use super::constraint::ConstraintKind;
+use super::integrator::Integrator;
+use super::sensor::SensorEventKind;
+use super::navigation::PathResult;
+
+/// Version of the format snapshots are written in. Bump it whenever the layout changes, and add
+/// a migration bringing the previous version up to date.
+pub const SNAPSHOT_VERSION : u32 = 2;
+
+/// First bytes of every snapshot
+const MAGIC : [u8; 4] = *b"ECSW";
+
+/// Turns the body of a snapshot written in one version into the body the next version would have written
+type Migration = fn(&[u8]) -> Result<Vec<u8>, String>;
+
+/// MIGRATIONS[n - 1] brings version n up to version n + 1, so older snapshots are migrated step
+/// by step until they're current.
+const MIGRATIONS : &[Migration] = &[wander_streams];
+
+/// Copies a value over from one body to the other as it is.
+fn copy<T : Save>(r : &mut Reader, w : &mut Writer) -> Result<(), String> {
+    T::load(r)?.save(w);
+    Ok(())
+}
+
+/// Copies a list over, passing every item through item.
+fn copy_vec(r : &mut Reader, w : &mut Writer, item : impl Fn(&mut Reader, &mut Writer) -> Result<(), String>) -> Result<(), String> {
+    let len = usize::load(r)?;
+    len.save(w);
+    for _ in 0..len {
+        item(r, w)?;
+    }
+    Ok(())
+}
+
+/// Copies a whole body over, passing every steering, the entities' and the prefabs', through
+/// steering. Everything else is copied through the types of this build, so this only reads
+/// bodies laid out like version 1 apart from their steering.
+fn map_steerings(body : &[u8], steering : fn(&mut Reader, &mut Writer) -> Result<(), String>) -> Result<Vec<u8>, String> {
+    let (r, w) = (&mut Reader::new(body), &mut Writer::new());
+    copy::<u64>(r, w)?;
+    copy::<Randomness>(r, w)?;
+    copy::<PhysicsConfig>(r, w)?;
+    copy::<Broadphase>(r, w)?;
+    copy::<EntityGrid>(r, w)?;
+    copy::<EntityGrid>(r, w)?;
+    copy::<Vec<CompFlag>>(r, w)?;
+    copy::<Vec<EntityId>>(r, w)?;
+    copy::<Vec<Position>>(r, w)?;
+    copy::<Vec<Velocity>>(r, w)?;
+    copy::<Vec<Orientation>>(r, w)?;
+    copy::<Vec<AngularVelocity>>(r, w)?;
+    copy::<Vec<Force>>(r, w)?;
+    copy::<Vec<Asset>>(r, w)?;
+    copy::<Vec<Collider>>(r, w)?;
+    copy::<Vec<Sensor>>(r, w)?;
+    copy::<Vec<BoundaryPolicy>>(r, w)?;
+    copy::<Vec<Rest>>(r, w)?;
+    copy::<Vec<Mass>>(r, w)?;
+    copy::<Vec<f32>>(r, w)?;
+    copy_vec(r, w, steering)?;
+    copy::<Vec<ParticleEmitter>>(r, w)?;
+    copy::<Vec<Lifetime>>(r, w)?;
+    copy::<Vec<Tweening>>(r, w)?;
+    copy::<Vec<StateMachine>>(r, w)?;
+    copy::<Vec<Spawner>>(r, w)?;
+    copy::<Vec<Option<EntityId>>>(r, w)?;
+    copy::<Vec<SensorEvent>>(r, w)?;
+    copy::<Vec<TweenEvent>>(r, w)?;
+    copy::<Vec<Constraint>>(r, w)?;
+    copy::<Option<WorldBounds>>(r, w)?;
+    copy::<Option<Attraction>>(r, w)?;
+    copy::<Option<Flocking>>(r, w)?;
+    copy::<Option<Fluid>>(r, w)?;
+    copy::<Option<Tilemap>>(r, w)?;
+    copy::<Option<Navigation>>(r, w)?;
+    copy::<ParticlePool>(r, w)?;
+    copy_vec(r, w, |r, w| {
+        copy::<CompFlag>(r, w)?;
+        copy::<Velocity>(r, w)?;
+        copy::<Orientation>(r, w)?;
+        copy::<AngularVelocity>(r, w)?;
+        copy::<Asset>(r, w)?;
+        copy::<Collider>(r, w)?;
+        copy::<Sensor>(r, w)?;
+        copy::<BoundaryPolicy>(r, w)?;
+        copy::<Mass>(r, w)?;
+        steering(r, w)?;
+        copy::<ParticleEmitter>(r, w)?;
+        copy::<Lifetime>(r, w)?;
+        copy::<Tweening>(r, w)?;
+        copy::<StateMachine>(r, w)
+    })?;
+    if r.remaining() > 0 {
+        return Err(format!("Snapshot has {} bytes left over", r.remaining()));
+    }
+    Ok(std::mem::take(&mut w.bytes))
+}
+
+/// Version 1 steering kept a 32 bit xorshift state for the wander jitter, zero until first
+/// used, where version 2 keeps a stream. A state in use carries on as the seed of one.
+fn wander_streams(body : &[u8]) -> Result<Vec<u8>, String> {
+    map_steerings(body, |r, w| {
+        copy::<Vec<(Behaviour, f32)>>(r, w)?;
+        copy::<(f32, f32, f32)>(r, w)?;
+        let (state, waypoint) : (u32, usize) = Save::load(r)?;
+        let rng = if state == 0 { None } else { Some(Stream::new(state as u64)) };
+        rng.save(w);
+        waypoint.save(w);
+        Ok(())
+    })
+}
+
+/// Bytes of a snapshot being written. Everything is little endian.
+pub(super) struct Writer {
+    bytes : Vec<u8>,
+}
+
+impl Writer {
+    fn new() -> Self {
+        Self { bytes : Vec::new() }
+    }
+
+    pub fn put(&mut self, bytes : &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// Which variant of an enum follows
+    pub fn tag(&mut self, tag : u8) {
+        self.bytes.push(tag);
+    }
+}
+
+/// Bytes of a snapshot being read, and how far along they've been read.
+pub(super) struct Reader<'a> {
+    bytes : &'a [u8],
+    at : usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes : &'a [u8]) -> Self {
+        Self { bytes, at : 0 }
+    }
+
+    pub fn take(&mut self, count : usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.at < count {
+            return Err(String::from("Snapshot ends too early"));
+        }
+        self.at += count;
+        Ok(&self.bytes[self.at - count..self.at])
+    }
+
+    pub fn tag(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.at
+    }
+}
+
+/// Something that goes into a snapshot and comes back out the same.
+pub(super) trait Save : Sized {
+    fn save(&self, w : &mut Writer);
+    fn load(r : &mut Reader) -> Result<Self, String>;
+}
+
+/// What an unknown enum tag reads as
+pub(super) fn unknown<T>(what : &str, tag : u8) -> Result<T, String> {
+    Err(format!("Unknown {} {} in snapshot", what, tag))
+}
+
+macro_rules! save_number {
+    ($($t:ty),*) => {$(
+        impl Save for $t {
+            fn save(&self, w : &mut Writer) {
+                w.put(&self.to_le_bytes());
+            }
+
+            fn load(r : &mut Reader) -> Result<Self, String> {
+                let mut bytes = [0; std::mem::size_of::<$t>()];
+                bytes.copy_from_slice(r.take(std::mem::size_of::<$t>())?);
+                Ok(<$t>::from_le_bytes(bytes))
+            }
+        }
+    )*};
+}
+
+save_number!(u8, u16, u32, u64, i32, f32);
+
+/// Saved as 64 bits, so snapshots don't depend on the platform
+impl Save for usize {
+    fn save(&self, w : &mut Writer) {
+        (*self as u64).save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        usize::try_from(u64::load(r)?).map_err(|_| String::from("Size in snapshot too large for this platform"))
+    }
+}
+
+impl Save for bool {
+    fn save(&self, w : &mut Writer) {
+        w.tag(*self as u8);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        match r.tag()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            tag => unknown("bool", tag),
+        }
+    }
+}
+
+impl Save for String {
+    fn save(&self, w : &mut Writer) {
+        self.len().save(w);
+        w.put(self.as_bytes());
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let len = usize::load(r)?;
+        String::from_utf8(r.take(len)?.to_vec()).map_err(|_| String::from("Text in snapshot isn't UTF-8"))
+    }
+}
+
+impl<T : Save> Save for Vec<T> {
+    fn save(&self, w : &mut Writer) {
+        self.len().save(w);
+        for item in self {
+            item.save(w);
+        }
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let len = usize::load(r)?;
+        // Every item takes at least a byte, so a broken length can't make this allocate much
+        let mut items = Vec::with_capacity(len.min(r.remaining()));
+        for _ in 0..len {
+            items.push(T::load(r)?);
+        }
+        Ok(items)
+    }
+}
+
+impl<T : Save> Save for VecDeque<T> {
+    fn save(&self, w : &mut Writer) {
+        self.len().save(w);
+        for item in self {
+            item.save(w);
+        }
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        Ok(Vec::load(r)?.into())
+    }
+}
+
+impl<T : Save> Save for Option<T> {
+    fn save(&self, w : &mut Writer) {
+        self.is_some().save(w);
+        if let Some(value) = self {
+            value.save(w);
+        }
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        Ok(if bool::load(r)? { Some(T::load(r)?) } else { None })
+    }
+}
+
+impl<A : Save, B : Save> Save for (A, B) {
+    fn save(&self, w : &mut Writer) {
+        self.0.save(w);
+        self.1.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        Ok((A::load(r)?, B::load(r)?))
+    }
+}
+
+impl<A : Save, B : Save, C : Save> Save for (A, B, C) {
+    fn save(&self, w : &mut Writer) {
+        self.0.save(w);
+        self.1.save(w);
+        self.2.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        Ok((A::load(r)?, B::load(r)?, C::load(r)?))
+    }
+}
+
+impl Save for [f32; 3] {
+    fn save(&self, w : &mut Writer) {
+        for c in self {
+            c.save(w);
+        }
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        Ok([f32::load(r)?, f32::load(r)?, f32::load(r)?])
+    }
+}
+
+impl Save for glm::Vec2 {
+    fn save(&self, w : &mut Writer) {
+        self.x.save(w);
+        self.y.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        Ok(glm::vec2(f32::load(r)?, f32::load(r)?))
+    }
+}
+
+impl Save for CompFlag {
+    fn save(&self, w : &mut Writer) {
+        self.bits().save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let bits = u32::load(r)?;
+        CompFlag::from_bits(bits).ok_or_else(|| format!("Unknown component flags {:#x} in snapshot", bits))
+    }
+}
+
+impl Save for Position {
+    fn save(&self, w : &mut Writer) {
+        (self.x, self.y).save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (x, y) = Save::load(r)?;
+        Ok(Position { x, y })
+    }
+}
+
+impl Save for Velocity {
+    fn save(&self, w : &mut Writer) {
+        (self.x, self.y).save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (x, y) = Save::load(r)?;
+        Ok(Velocity { x, y })
+    }
+}
+
+impl Save for Orientation {
+    fn save(&self, w : &mut Writer) {
+        self.angle.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        Ok(Orientation { angle : f32::load(r)? })
+    }
+}
+
+impl Save for AngularVelocity {
+    fn save(&self, w : &mut Writer) {
+        self.w.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        Ok(AngularVelocity { w : f32::load(r)? })
+    }
+}
+
+impl Save for Force {
+    fn save(&self, w : &mut Writer) {
+        (self.x, self.y, self.torque).save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (x, y, torque) = Save::load(r)?;
+        Ok(Force { x, y, torque })
+    }
+}
+
+impl Save for Mass {
+    fn save(&self, w : &mut Writer) {
+        self.value.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        Ok(Mass { value : f32::load(r)? })
+    }
+}
+
+impl Save for Asset {
+    fn save(&self, w : &mut Writer) {
+        self.texture.save(w);
+        self.colour.save(w);
+        self.scale.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        Ok(Asset { texture : Save::load(r)?, colour : Save::load(r)?, scale : Save::load(r)? })
+    }
+}
+
+impl Save for Shape {
+    fn save(&self, w : &mut Writer) {
+        match self {
+            Shape::Circle { radius } => {
+                w.tag(0);
+                radius.save(w);
+            },
+            Shape::Aabb { half_width, half_height } => {
+                w.tag(1);
+                (*half_width, *half_height).save(w);
+            },
+            Shape::Polygon(polygon) => {
+                w.tag(2);
+                polygon.vertices().to_vec().save(w);
+            },
+        }
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        match r.tag()? {
+            0 => Ok(Shape::Circle { radius : f32::load(r)? }),
+            1 => {
+                let (half_width, half_height) = Save::load(r)?;
+                Ok(Shape::Aabb { half_width, half_height })
+            },
+            2 => Ok(Shape::Polygon(Polygon::new(&Vec::load(r)?)?)),
+            tag => unknown("shape", tag),
+        }
+    }
+}
+
+impl Save for Collider {
+    fn save(&self, w : &mut Writer) {
+        self.shape.save(w);
+        self.layers.save(w);
+        (self.restitution, self.friction).save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let shape = Save::load(r)?;
+        let layers = Save::load(r)?;
+        let (restitution, friction) = Save::load(r)?;
+        Ok(Collider { shape, layers, restitution, friction })
+    }
+}
+
+impl Save for Sensor {
+    fn save(&self, w : &mut Writer) {
+        self.inside.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        Ok(Sensor { inside : Save::load(r)? })
+    }
+}
+
+impl Save for SensorEvent {
+    fn save(&self, w : &mut Writer) {
+        (self.sensor, self.other).save(w);
+        w.tag(self.kind as u8);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (sensor, other) = Save::load(r)?;
+        let kind = match r.tag()? {
+            0 => SensorEventKind::Enter,
+            1 => SensorEventKind::Stay,
+            2 => SensorEventKind::Exit,
+            tag => return unknown("sensor event", tag),
+        };
+        Ok(SensorEvent { sensor, other, kind })
+    }
+}
+
+impl Save for Constraint {
+    fn save(&self, w : &mut Writer) {
+        (self.a, self.b).save(w);
+        w.tag(self.kind as u8);
+        (self.rest_length, self.stiffness, self.damping).save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (a, b) = Save::load(r)?;
+        let kind = match r.tag()? {
+            0 => ConstraintKind::Distance,
+            1 => ConstraintKind::Spring,
+            2 => ConstraintKind::Pin,
+            tag => return unknown("constraint", tag),
+        };
+        let (rest_length, stiffness, damping) = Save::load(r)?;
+        Ok(Constraint { a, b, kind, rest_length, stiffness, damping })
+    }
+}
+
+impl Save for BoundaryPolicy {
+    fn save(&self, w : &mut Writer) {
+        w.tag(*self as u8);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        match r.tag()? {
+            0 => Ok(BoundaryPolicy::Wrap),
+            1 => Ok(BoundaryPolicy::Bounce),
+            2 => Ok(BoundaryPolicy::Clamp),
+            3 => Ok(BoundaryPolicy::Despawn),
+            tag => unknown("boundary policy", tag),
+        }
+    }
+}
+
+impl Save for WorldBounds {
+    fn save(&self, w : &mut Writer) {
+        (self.min, self.max, self.policy).save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (min, max, policy) = Save::load(r)?;
+        Ok(WorldBounds { min, max, policy })
+    }
+}
+
+impl Save for Rest {
+    fn save(&self, w : &mut Writer) {
+        (self.anchor.clone(), self.time).save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (anchor, time) = Save::load(r)?;
+        Ok(Rest { anchor, time })
+    }
+}
+
+impl Save for Target {
+    fn save(&self, w : &mut Writer) {
+        match self {
+            Target::Point(point) => {
+                w.tag(0);
+                point.save(w);
+            },
+            Target::Entity(id) => {
+                w.tag(1);
+                id.save(w);
+            },
+        }
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        match r.tag()? {
+            0 => Ok(Target::Point(Save::load(r)?)),
+            1 => Ok(Target::Entity(Save::load(r)?)),
+            tag => unknown("steering target", tag),
+        }
+    }
+}
+
+impl Save for Behaviour {
+    fn save(&self, w : &mut Writer) {
+        match self {
+            Behaviour::Seek(target) => {
+                w.tag(0);
+                target.save(w);
+            },
+            Behaviour::Flee { target, radius } => {
+                w.tag(1);
+                target.save(w);
+                radius.save(w);
+            },
+            Behaviour::Arrive { target, slowing_radius } => {
+                w.tag(2);
+                target.save(w);
+                slowing_radius.save(w);
+            },
+            Behaviour::Wander { distance, radius, jitter } => {
+                w.tag(3);
+                (*distance, *radius, *jitter).save(w);
+            },
+            Behaviour::Pursue(id) => {
+                w.tag(4);
+                id.save(w);
+            },
+            Behaviour::AvoidObstacles { look_ahead } => {
+                w.tag(5);
+                look_ahead.save(w);
+            },
+            Behaviour::FollowFlow(field) => {
+                w.tag(6);
+                field.save(w);
+            },
+            Behaviour::FollowPath { waypoints, reach, slowing_radius } => {
+                w.tag(7);
+                waypoints.save(w);
+                (*reach, *slowing_radius).save(w);
+            },
+        }
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        match r.tag()? {
+            0 => Ok(Behaviour::Seek(Save::load(r)?)),
+            1 => Ok(Behaviour::Flee { target : Save::load(r)?, radius : Save::load(r)? }),
+            2 => Ok(Behaviour::Arrive { target : Save::load(r)?, slowing_radius : Save::load(r)? }),
+            3 => {
+                let (distance, radius, jitter) = Save::load(r)?;
+                Ok(Behaviour::Wander { distance, radius, jitter })
+            },
+            4 => Ok(Behaviour::Pursue(Save::load(r)?)),
+            5 => Ok(Behaviour::AvoidObstacles { look_ahead : Save::load(r)? }),
+            6 => Ok(Behaviour::FollowFlow(Save::load(r)?)),
+            7 => {
+                let waypoints = Save::load(r)?;
+                let (reach, slowing_radius) = Save::load(r)?;
+                Ok(Behaviour::FollowPath { waypoints, reach, slowing_radius })
+            },
+            tag => unknown("steering behaviour", tag),
+        }
+    }
+}
+
+impl Save for Lifetime {
+    fn save(&self, w : &mut Writer) {
+        (self.remaining, self.on_expire).save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (remaining, on_expire) = Save::load(r)?;
+        Ok(Lifetime { remaining, on_expire })
+    }
+}
+
+impl Save for Ease {
+    fn save(&self, w : &mut Writer) {
+        w.tag(*self as u8);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        const EASES : [Ease; 11] = [
+            Ease::Linear, Ease::QuadIn, Ease::QuadOut, Ease::QuadInOut, Ease::CubicIn, Ease::CubicOut,
+            Ease::CubicInOut, Ease::ElasticIn, Ease::ElasticOut, Ease::BounceIn, Ease::BounceOut,
+        ];
+        let tag = r.tag()?;
+        EASES.get(tag as usize).copied().map_or_else(|| unknown("ease", tag), Ok)
+    }
+}
+
+impl Save for Property {
+    fn save(&self, w : &mut Writer) {
+        match self {
+            Property::Position { from, to } => {
+                w.tag(0);
+                (*from, *to).save(w);
+            },
+            Property::Colour { from, to } => {
+                w.tag(1);
+                (*from, *to).save(w);
+            },
+            Property::Scale { from, to } => {
+                w.tag(2);
+                (*from, *to).save(w);
+            },
+            Property::Rotation { from, to } => {
+                w.tag(3);
+                (*from, *to).save(w);
+            },
+        }
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        match r.tag()? {
+            0 => Ok(Property::Position { from : Save::load(r)?, to : Save::load(r)? }),
+            1 => Ok(Property::Colour { from : Save::load(r)?, to : Save::load(r)? }),
+            2 => Ok(Property::Scale { from : Save::load(r)?, to : Save::load(r)? }),
+            3 => Ok(Property::Rotation { from : Save::load(r)?, to : Save::load(r)? }),
+            tag => unknown("tweened property", tag),
+        }
+    }
+}
+
+impl Save for Tween {
+    fn save(&self, w : &mut Writer) {
+        match self {
+            Tween::Animate { property, duration, ease } => {
+                w.tag(0);
+                property.save(w);
+                duration.save(w);
+                ease.save(w);
+            },
+            Tween::Wait(duration) => {
+                w.tag(1);
+                duration.save(w);
+            },
+            Tween::Sequence(tweens) => {
+                w.tag(2);
+                tweens.save(w);
+            },
+            Tween::Parallel(tweens) => {
+                w.tag(3);
+                tweens.save(w);
+            },
+        }
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        match r.tag()? {
+            0 => Ok(Tween::Animate { property : Save::load(r)?, duration : Save::load(r)?, ease : Save::load(r)? }),
+            1 => Ok(Tween::Wait(Save::load(r)?)),
+            2 => Ok(Tween::Sequence(Save::load(r)?)),
+            3 => Ok(Tween::Parallel(Save::load(r)?)),
+            tag => unknown("tween", tag),
+        }
+    }
+}
+
+impl Save for Repeat {
+    fn save(&self, w : &mut Writer) {
+        match self {
+            Repeat::Once => w.tag(0),
+            Repeat::Loop(times) => {
+                w.tag(1);
+                times.save(w);
+            },
+            Repeat::PingPong(times) => {
+                w.tag(2);
+                times.save(w);
+            },
+        }
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        match r.tag()? {
+            0 => Ok(Repeat::Once),
+            1 => Ok(Repeat::Loop(Save::load(r)?)),
+            2 => Ok(Repeat::PingPong(Save::load(r)?)),
+            tag => unknown("repeat", tag),
+        }
+    }
+}
+
+impl Save for Tweening {
+    fn save(&self, w : &mut Writer) {
+        self.tween.save(w);
+        self.repeat.save(w);
+        (self.tag, self.elapsed).save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let tween = Save::load(r)?;
+        let repeat = Save::load(r)?;
+        let (tag, elapsed) = Save::load(r)?;
+        Ok(Tweening { tween, repeat, tag, elapsed })
+    }
+}
+
+impl Save for TweenEvent {
+    fn save(&self, w : &mut Writer) {
+        (self.entity, self.tag).save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (entity, tag) = Save::load(r)?;
+        Ok(TweenEvent { entity, tag })
+    }
+}
+
+impl Save for SpawnArea {
+    fn save(&self, w : &mut Writer) {
+        match self {
+            SpawnArea::Point => w.tag(0),
+            SpawnArea::Rect { half_width, half_height } => {
+                w.tag(1);
+                (*half_width, *half_height).save(w);
+            },
+            SpawnArea::Circle { radius } => {
+                w.tag(2);
+                radius.save(w);
+            },
+            SpawnArea::Path(points) => {
+                w.tag(3);
+                points.save(w);
+            },
+        }
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        match r.tag()? {
+            0 => Ok(SpawnArea::Point),
+            1 => {
+                let (half_width, half_height) = Save::load(r)?;
+                Ok(SpawnArea::Rect { half_width, half_height })
+            },
+            2 => Ok(SpawnArea::Circle { radius : Save::load(r)? }),
+            3 => Ok(SpawnArea::Path(Save::load(r)?)),
+            tag => unknown("spawn area", tag),
+        }
+    }
+}
+
+impl Save for Schedule {
+    fn save(&self, w : &mut Writer) {
+        match self {
+            Schedule::Rate(rate) => {
+                w.tag(0);
+                rate.save(w);
+            },
+            Schedule::Waves(waves) => {
+                w.tag(1);
+                waves.len().save(w);
+                for wave in waves {
+                    (wave.start, wave.count, wave.interval).save(w);
+                }
+            },
+        }
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        match r.tag()? {
+            0 => Ok(Schedule::Rate(Save::load(r)?)),
+            1 => {
+                let waves : Vec<(f32, u32, f32)> = Save::load(r)?;
+                Ok(Schedule::Waves(waves.into_iter().map(|(start, count, interval)| Wave { start, count, interval }).collect()))
+            },
+            tag => unknown("spawn schedule", tag),
+        }
+    }
+}
+
+impl Save for Attraction {
+    fn save(&self, w : &mut Writer) {
+        (self.gravitational_constant, self.theta, self.softening).save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (gravitational_constant, theta, softening) = Save::load(r)?;
+        Ok(Attraction { gravitational_constant, theta, softening })
+    }
+}
+
+impl Save for Flocking {
+    fn save(&self, w : &mut Writer) {
+        (self.separation, self.alignment, self.cohesion).save(w);
+        (self.view_radius, self.min_speed, self.max_speed).save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (separation, alignment, cohesion) = Save::load(r)?;
+        let (view_radius, min_speed, max_speed) = Save::load(r)?;
+        Ok(Flocking { separation, alignment, cohesion, view_radius, min_speed, max_speed })
+    }
+}
+
+impl Save for Fluid {
+    fn save(&self, w : &mut Writer) {
+        (self.kernel_radius, self.rest_density).save(w);
+        (self.stiffness, self.viscosity).save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (kernel_radius, rest_density) = Save::load(r)?;
+        let (stiffness, viscosity) = Save::load(r)?;
+        Ok(Fluid { kernel_radius, rest_density, stiffness, viscosity })
+    }
+}
+
+impl Save for PhysicsConfig {
+    fn save(&self, w : &mut Writer) {
+        self.gravity.save(w);
+        (self.linear_damping, self.angular_damping).save(w);
+        w.tag(self.integrator as u8);
+        (self.time_step, self.constraint_iterations).save(w);
+        (self.sleep_velocity, self.time_to_sleep).save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let gravity = Save::load(r)?;
+        let (linear_damping, angular_damping) = Save::load(r)?;
+        let integrator = match r.tag()? {
+            0 => Integrator::SemiImplicitEuler,
+            1 => Integrator::VelocityVerlet,
+            tag => return unknown("integrator", tag),
+        };
+        let (time_step, constraint_iterations) = Save::load(r)?;
+        let (sleep_velocity, time_to_sleep) = Save::load(r)?;
+        Ok(PhysicsConfig { gravity, linear_damping, angular_damping, integrator, time_step, constraint_iterations, sleep_velocity, time_to_sleep })
+    }
+}
+
+impl Save for SweepAxis {
+    fn save(&self, w : &mut Writer) {
+        w.tag(*self as u8);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        match r.tag()? {
+            0 => Ok(SweepAxis::X),
+            1 => Ok(SweepAxis::Y),
+            tag => unknown("sweep axis", tag),
+        }
+    }
+}
+
+impl Save for Tileset {
+    fn save(&self, w : &mut Writer) {
+        self.texture.save(w);
+        (self.columns, self.rows).save(w);
+        self.blocking.save(w);
+        self.surface.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let texture = Save::load(r)?;
+        let (columns, rows) = Save::load(r)?;
+        Ok(Tileset { texture, columns, rows, blocking : Save::load(r)?, surface : Save::load(r)? })
+    }
+}
+
+impl Save for PathResult {
+    fn save(&self, w : &mut Writer) {
+        self.id.save(w);
+        self.path.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        Ok(PathResult { id : Save::load(r)?, path : Save::load(r)? })
+    }
+}
+
+impl Save for Prefab {
+    fn save(&self, w : &mut Writer) {
+        self.components.save(w);
+        self.velocity.save(w);
+        self.orientation.save(w);
+        self.angular_velocity.save(w);
+        self.asset.save(w);
+        self.collider.save(w);
+        self.sensor.save(w);
+        self.boundary_policy.save(w);
+        self.mass.save(w);
+        self.steering.save(w);
+        self.emitter.save(w);
+        self.lifetime.save(w);
+        self.tweening.save(w);
+        self.state_machine.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        Ok(Prefab {
+            components : Save::load(r)?,
+            velocity : Save::load(r)?,
+            orientation : Save::load(r)?,
+            angular_velocity : Save::load(r)?,
+            asset : Save::load(r)?,
+            collider : Save::load(r)?,
+            sensor : Save::load(r)?,
+            boundary_policy : Save::load(r)?,
+            mass : Save::load(r)?,
+            steering : Save::load(r)?,
+            emitter : Save::load(r)?,
+            lifetime : Save::load(r)?,
+            tweening : Save::load(r)?,
+            state_machine : Save::load(r)?,
+        })
+    }
+}
+
+/// A storage with one entry per entity
+fn load_storage<T : Save>(r : &mut Reader, count : usize, what : &str) -> Result<Vec<T>, String> {
+    let storage : Vec<T> = Save::load(r)?;
+    if storage.len() != count {
+        return Err(format!("Snapshot has {} {} for {} entities", storage.len(), what, count));
+    }
+    Ok(storage)
+}
+
+/// Fails if any of the ids isn't one of the count entities.
+fn check_ids(ids : impl IntoIterator<Item = EntityId>, count : usize, what : &str) -> Result<(), String> {
+    match ids.into_iter().find(|id| *id as usize >= count) {
+        Some(id) => Err(format!("Snapshot has {} naming entity {}, but only {} entities", what, id, count)),
+        None => Ok(()),
+    }
+}
+
+/// Every entity id the loaded world holds on to has to name one of its entities, or the first
+/// update to follow it would index out of bounds.
+fn check_references(game : &Game) -> Result<(), String> {
+    let count = game.entities.len();
+    check_ids(game.free_ids.iter().copied(), count, "free ids")?;
+    check_ids(game.constraints.iter().flat_map(|c| [c.a, c.b]), count, "constraints")?;
+    check_ids(game.sensors.iter().flat_map(|s| s.inside.iter().copied()), count, "sensors")?;
+    let steerings = game.steerings.iter().chain(game.prefabs.iter().map(|x| &x.steering));
+    check_ids(steerings.flat_map(|s| s.behaviours.iter().filter_map(|(b, _)| b.entity())), count, "steering targets")?;
+    check_ids(game.spawned_by.iter().flatten().copied(), count, "spawned entities")?;
+    check_ids(game.spawners.iter().flat_map(|s| s.live().iter().copied()), count, "spawners")?;
+    check_ids(game.sensor_events.iter().flat_map(|e| [e.sensor, e.other]), count, "sensor events")?;
+    check_ids(game.tween_events.iter().map(|e| e.entity), count, "tween events")?;
+    for (grid, what) in [(&game.spacially_sorted, "grid"), (&game.static_sorted, "static grid")] {
+        if grid.tracked() > count {
+            return Err(format!("Snapshot has a {} locating {} entities, but only {} entities", what, grid.tracked(), count));
+        }
+        check_ids(grid.sorted_ids(), count, what)?;
+    }
+    check_ids(game.broadphase.entity_ids().iter().copied(), count, "broadphase")?;
+    if let Some(tilemap) = &game.tilemap {
+        check_ids(tilemap.collider_ids(), count, "tile colliders")?;
+    }
+    Ok(())
+}
+
+impl Game {
+    /// Saves the whole world to bytes load_snapshot can read back, in the version this build
+    /// writes. Machines are code, so only which machine and state each entity is in is saved.
+    pub fn save_snapshot(&self) -> Vec<u8> {
+        let mut writer = Writer::new();
+        let w = &mut writer;
+        w.put(&MAGIC);
+        SNAPSHOT_VERSION.save(w);
+
+        self.tick.save(w);
+        self.random.save(w);
+        self.physics.save(w);
+        self.broadphase.save(w);
+        self.spacially_sorted.save(w);
+        self.static_sorted.save(w);
+
+        self.entities.iter().map(|x| x.components).collect::<Vec<_>>().save(w);
+        self.free_ids.save(w);
+        self.positions.save(w);
+        self.velocities.save(w);
+        self.orientations.save(w);
+        self.angular_velocities.save(w);
+        self.forces.save(w);
+        self.assets.save(w);
+        self.colliders.save(w);
+        self.sensors.save(w);
+        self.boundary_policies.save(w);
+        self.rests.save(w);
+        self.masses.save(w);
+        self.densities.save(w);
+        self.steerings.save(w);
+        self.emitters.save(w);
+        self.lifetimes.save(w);
+        self.tweenings.save(w);
+        self.state_machines.save(w);
+        self.spawners.save(w);
+        self.spawned_by.save(w);
+
+        self.sensor_events.save(w);
+        self.tween_events.save(w);
+        self.constraints.save(w);
+        self.bounds.save(w);
+        self.attraction.save(w);
+        self.flocking.save(w);
+        self.fluid.save(w);
+        self.tilemap.save(w);
+        self.navigation.save(w);
+        self.particles.save(w);
+        self.prefabs.save(w);
+        writer.bytes
+    }
+
+    /// Replaces the world with a snapshot, migrating it first if an older version wrote it. The
+    /// game keeps its machines, which have to be the ones the snapshot's state machines were
+    /// running, and starts a new checksum log if it keeps one. Nothing changes if loading fails.
+    pub fn load_snapshot(&mut self, bytes : &[u8]) -> Result<(), String> {
+        if bytes.len() < 8 || bytes[..4] != MAGIC {
+            return Err(String::from("Not a snapshot"));
+        }
+        let version = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        if version == 0 || version > SNAPSHOT_VERSION {
+            return Err(format!("Snapshot version {} can't be read, this build reads up to version {}", version, SNAPSHOT_VERSION));
+        }
+        let mut body = bytes[8..].to_vec();
+        for from in version..SNAPSHOT_VERSION {
+            let migrate = MIGRATIONS.get(from as usize - 1).ok_or_else(|| format!("No migration from snapshot version {}", from))?;
+            body = migrate(&body)?;
+        }
+        let r = &mut Reader::new(&body);
+
+        let mut game = Game::new();
+        game.tick = Save::load(r)?;
+        game.random = Save::load(r)?;
+        game.physics = Save::load(r)?;
+        game.broadphase = Save::load(r)?;
+        game.spacially_sorted = Save::load(r)?;
+        game.static_sorted = Save::load(r)?;
+
+        let components : Vec<CompFlag> = Save::load(r)?;
+        let count = components.len();
+        game.entities = components.into_iter().enumerate().map(|(id, components)| Entity { id : id as EntityId, components }).collect();
+        game.free_ids = Save::load(r)?;
+        game.positions = load_storage(r, count, "positions")?;
+        game.velocities = load_storage(r, count, "velocities")?;
+        game.orientations = load_storage(r, count, "orientations")?;
+        game.angular_velocities = load_storage(r, count, "angular velocities")?;
+        game.forces = load_storage(r, count, "forces")?;
+        game.assets = load_storage(r, count, "assets")?;
+        game.colliders = load_storage(r, count, "colliders")?;
+        game.sensors = load_storage(r, count, "sensors")?;
+        game.boundary_policies = load_storage(r, count, "boundary policies")?;
+        game.rests = load_storage(r, count, "rests")?;
+        game.masses = load_storage(r, count, "masses")?;
+        game.densities = load_storage(r, count, "densities")?;
+        game.steerings = load_storage(r, count, "steerings")?;
+        game.emitters = load_storage(r, count, "emitters")?;
+        game.lifetimes = load_storage(r, count, "lifetimes")?;
+        game.tweenings = load_storage(r, count, "tweenings")?;
+        game.state_machines = load_storage(r, count, "state machines")?;
+        game.spawners = load_storage(r, count, "spawners")?;
+        game.spawned_by = load_storage(r, count, "spawners of entities")?;
+        // Only scratch space during an update
+        game.collision_buffer_pos = game.positions.clone();
+        game.collision_buffer_vel = game.velocities.clone();
+        game.collision_buffer_ang = game.angular_velocities.clone();
+
+        game.sensor_events = Save::load(r)?;
+        game.tween_events = Save::load(r)?;
+        game.constraints = Save::load(r)?;
+        game.bounds = Save::load(r)?;
+        game.attraction = Save::load(r)?;
+        game.flocking = Save::load(r)?;
+        game.fluid = Save::load(r)?;
+        game.tilemap = Save::load(r)?;
+        game.navigation = Save::load(r)?;
+        game.particles = Save::load(r)?;
+        game.prefabs = Save::load(r)?;
+        if r.remaining() > 0 {
+            return Err(format!("Snapshot has {} bytes left over", r.remaining()));
+        }
+        check_references(&game)?;
+
+        let running = game.entities.iter()
+            .filter(|x| x.components.contains(CompFlag::Fsm))
+            .map(|x| &game.state_machines[x.id as usize])
+            .chain(game.prefabs.iter().filter(|x| x.components.contains(CompFlag::Fsm)).map(|x| &x.state_machine));
+        for fsm in running {
+            let states = self.machines.get(fsm.machine).map(|x| x.states().len());
+            if states.is_none() || fsm.current().is_some_and(|x| Some(x) >= states) {
+                return Err(format!("Snapshot runs machine {}, which the game doesn't have as it was saved", fsm.machine));
+            }
+        }
+        game.machines = std::mem::take(&mut self.machines);
+        game.checksums = self.checksums.as_ref().map(|x| ChecksumLog::new(x.capacity));
+        *self = game;
+        Ok(())
+    }
+
+    pub fn save_snapshot_to(&self, path : &std::path::Path) -> Result<(), String> {
+        std::fs::write(path, self.save_snapshot()).map_err(|e| format!("Couldn't write snapshot {}: {}", path.display(), e))
+    }
+
+    pub fn load_snapshot_from(&mut self, path : &std::path::Path) -> Result<(), String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("Couldn't read snapshot {}: {}", path.display(), e))?;
+        self.load_snapshot(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::super::tilemap::TileId;
+    use super::super::{Machine, Steering, SweepAndPrune};
+    use super::*;
+
+    /// Guards wander for a while, then chase the thief for a while
+    fn guard_machine(thief : EntityId) -> Machine {
+        let mut machine = Machine::new("guard");
+        let (patrol, chase) = (machine.add_state("patrol"), machine.add_state("chase"));
+        machine.on_enter(patrol, |game, id| {
+            game.steerings[id as usize] = Steering::new(1.0, 0.3)
+                .with(Behaviour::Wander { distance : 0.1, radius : 0.05, jitter : 10.0 }, 1.0);
+        });
+        machine.on_enter(chase, move |game, id| {
+            game.steerings[id as usize] = Steering::new(2.0, 0.4).with(Behaviour::Pursue(thief), 1.0);
+        });
+        machine.add_transition(patrol, chase, |game, id| game.state_machines[id as usize].time_in_state() > 0.3);
+        machine.add_transition(chase, patrol, |game, id| game.state_machines[id as usize].time_in_state() > 0.2);
+        machine
+    }
+
+    /// Something of every system that keeps state between updates
+    fn busy_world(broadphase : Broadphase) -> Game {
+        let mut game = Game::new();
+        game.random = Randomness::new(42);
+        game.broadphase = broadphase;
+        game.physics.gravity = glm::vec2(0.0, -0.2);
+
+        let thief = game.add_entity(CompFlag::Pos | CompFlag::Twn);
+        let corners = [glm::vec2(-0.5, -0.5), glm::vec2(0.5, 0.5)];
+        game.tweenings[thief as usize] = Tweening::new(
+            Tween::new(Property::Position { from : corners[0], to : corners[1] }, 0.5, Ease::QuadInOut), Repeat::PingPong(None), 0);
+        let machine = game.add_machine(guard_machine(thief));
+        let mut rng = game.random.stream("guards");
+        let mut guards = Vec::new();
+        for _ in 0..10 {
+            let id = game.add_entity(CompFlag::Pos | CompFlag::Vel | CompFlag::Col | CompFlag::Str | CompFlag::Fsm);
+            game.positions[id as usize] = Position { x : rng.gen::<f32>() - 0.5, y : rng.gen::<f32>() - 0.5 };
+            game.colliders[id as usize].shape = Shape::Circle { radius : 0.03 };
+            game.state_machines[id as usize] = StateMachine::new(machine);
+            guards.push(id);
+        }
+        game.constraints.push(Constraint::spring(guards[0], guards[1], 0.2, 5.0, 0.5));
+
+        let mut spark = Prefab::new(CompFlag::Pos | CompFlag::Vel | CompFlag::Col | CompFlag::Ttl | CompFlag::Str);
+        spark.collider.shape = Shape::Circle { radius : 0.01 };
+        spark.lifetime = Lifetime::new(0.4);
+        spark.steering = Steering::new(1.0, 0.5).with(Behaviour::Wander { distance : 0.1, radius : 0.05, jitter : 20.0 }, 1.0);
+        let spark = game.add_prefab(spark);
+        let fountain = game.add_entity(CompFlag::Pos | CompFlag::Spw);
+        let mut spawner = Spawner::new(spark, Schedule::Rate(40.0), SpawnArea::Circle { radius : 0.1 }, game.random.seed_for("sparks"));
+        spawner.velocity_spread = 0.3;
+        spawner.max_live = Some(12);
+        game.spawners[fountain as usize] = spawner;
+
+        let smoke = game.add_entity(CompFlag::Pos | CompFlag::Emt);
+        game.emitters[smoke as usize] = ParticleEmitter::new(game.random.seed_for("smoke"));
+        let sensor = game.add_entity(CompFlag::Pos | CompFlag::Col | CompFlag::Sen);
+        game.colliders[sensor as usize].shape = Shape::Circle { radius : 0.3 };
+        game
+    }
+
+    #[test]
+    fn loaded_worlds_simulate_like_the_saved_ones() {
+        let (mut tx, _rx) = std::sync::mpsc::sync_channel(1);
+        for broadphase in [Broadphase::Grid, Broadphase::SweepAndPrune(SweepAndPrune::new(SweepAxis::X))] {
+            let mut game = busy_world(broadphase);
+            for _ in 0..60 {
+                game.update(&mut tx);
+            }
+            assert!(!game.particles.is_empty() && !game.spawners[11].live().is_empty(), "The world should be busy by now");
+            let saved = game.save_snapshot();
+
+            let mut loaded = Game::new();
+            loaded.add_machine(guard_machine(0));
+            loaded.load_snapshot(&saved).unwrap();
+            assert_eq!(loaded.save_snapshot(), saved);
+            assert_eq!(loaded.checksum(), game.checksum());
+            for tick in 0..120 {
+                game.update(&mut tx);
+                loaded.update(&mut tx);
+                assert_eq!(loaded.checksum(), game.checksum(), "Went apart {} ticks after loading", tick + 1);
+            }
+            assert_eq!(loaded.save_snapshot(), game.save_snapshot());
+        }
+    }
+
+    #[test]
+    fn snapshots_naming_missing_entities_are_turned_away() {
+        let mut game = Game::new();
+        let a = game.add_entity(CompFlag::Pos | CompFlag::Vel);
+        let b = game.add_entity(CompFlag::Pos | CompFlag::Vel);
+        game.constraints.push(Constraint::distance(a, b, 0.1));
+        let mut loaded = Game::new();
+        loaded.load_snapshot(&game.save_snapshot()).unwrap();
+        assert_eq!(loaded.entities.len(), 2);
+
+        game.constraints.push(Constraint::distance(a, 7, 0.1));
+        let error = loaded.load_snapshot(&game.save_snapshot()).unwrap_err();
+        assert!(error.contains("constraints"), "{}", error);
+        assert_eq!(loaded.constraints.len(), 1, "A refused snapshot shouldn't change the world");
+
+        game.constraints.pop();
+        game.sensors[a as usize].inside.push(2);
+        assert!(loaded.load_snapshot(&game.save_snapshot()).unwrap_err().contains("sensors"));
+
+        game.sensors[a as usize].inside.clear();
+        game.spacially_sorted.sort_single(50, &Position { x : 0.0, y : 0.0 });
+        assert!(loaded.load_snapshot(&game.save_snapshot()).unwrap_err().contains("grid"));
+        game.spacially_sorted.remove(50);
+        assert!(loaded.load_snapshot(&game.save_snapshot()).unwrap_err().contains("locating 51 entities"));
+    }
+
+    #[test]
+    fn version_1_snapshots_carry_their_wander_state_over() {
+        let mut game = Game::new();
+        let seeded = game.add_entity(CompFlag::Pos | CompFlag::Vel | CompFlag::Str);
+        let wander = Behaviour::Wander { distance : 0.1, radius : 0.05, jitter : 10.0 };
+        game.steerings[seeded as usize] = Steering::new(1.0, 0.5).with(wander.clone(), 1.0);
+        game.steerings[seeded as usize].reseed(12_345);
+        game.add_entity(CompFlag::Pos | CompFlag::Vel | CompFlag::Str);
+        game.prefabs.push(Prefab { steering : Steering::new(1.0, 0.5).with(wander, 1.0), ..Prefab::default() });
+        let current = game.save_snapshot();
+
+        // Written the way version 1 was, with the streams back as xorshift states
+        let body = map_steerings(&current[8..], |r, w| {
+            copy::<Vec<(Behaviour, f32)>>(r, w)?;
+            copy::<(f32, f32, f32)>(r, w)?;
+            let (rng, waypoint) : (Option<Stream>, usize) = Save::load(r)?;
+            (rng.map_or(0, |x| x.state() as u32), waypoint).save(w);
+            Ok(())
+        }).unwrap();
+        let mut old = MAGIC.to_vec();
+        old.extend_from_slice(&1u32.to_le_bytes());
+        old.extend_from_slice(&body);
+        assert_ne!(old.len(), current.len());
+
+        let mut loaded = Game::new();
+        loaded.load_snapshot(&old).unwrap();
+        assert_eq!(loaded.save_snapshot(), current);
+    }
+
+    #[test]
+    fn tilemap_sizes_are_checked_against_its_tiles_before_allocating() {
+        let mut w = Writer::new();
+        (glm::vec2(0.0, 0.0), 1.0f32).save(&mut w);
+        (usize::MAX, 2usize).save(&mut w);
+        Tileset::new("/tiles.png", 1, 1).save(&mut w);
+        Vec::<TileId>::new().save(&mut w);
+        Vec::<Option<EntityId>>::new().save(&mut w);
+        let error = Tilemap::load(&mut Reader::new(&w.bytes)).unwrap_err();
+        assert!(error.contains("0 tiles"), "{}", error);
+    }
+}
@@ -0,0 +1,226 @@
+
+use rand::Rng;
+
+use super::{Checksum, CompFlag, EntityId, Game, Position, PrefabId, Stream};
+use super::snapshot::{Reader, Save, Writer};
+
+/// Where around the spawner copies show up
+#[derive(Debug, Clone)]
+pub enum SpawnArea {
+    Point,
+    Rect { half_width : f32, half_height : f32 },
+    Circle { radius : f32 },
+    /// Anywhere along the line through these points, relative to the spawner
+    Path(Vec<glm::Vec2>),
+}
+
+/// A batch of copies starting at a set time
+#[derive(Debug, Clone)]
+pub struct Wave {
+    /// Seconds after the spawner started
+    pub start : f32,
+    pub count : u32,
+    /// Seconds between two copies of the wave. 0 spawns the whole wave at once.
+    pub interval : f32,
+}
+
+#[derive(Debug, Clone)]
+pub enum Schedule {
+    /// Copies per second, for as long as the spawner lives
+    Rate(f32),
+    Waves(Vec<Wave>),
+}
+
+/// Spawns copies of a prefab around its entity's position. Wave copies held back by max_live are
+/// spawned once there's room again, copies due at a rate are skipped.
+#[derive(Debug, Clone)]
+pub struct Spawner {
+    pub prefab : PrefabId,
+    pub schedule : Schedule,
+    pub area : SpawnArea,
+    /// No more copies than this are alive at once. Unlimited if None.
+    pub max_live : Option<usize>,
+    /// Each velocity component of a copy gets up to this much added either way
+    pub velocity_spread : f32,
+    rng : Stream,
+    /// Seconds since the spawner started
+    elapsed : f32,
+    /// Fractions of a copy owed from earlier updates at a rate
+    owed : f32,
+    /// How many copies of each wave were spawned
+    spawned : Vec<u32>,
+    /// Copies that might still be alive. Ids despawned and reused since are weeded out by spawned_by.
+    live : Vec<EntityId>,
+}
+
+impl Default for Spawner {
+    fn default() -> Self {
+        Self::new(0, Schedule::Waves(Vec::new()), SpawnArea::Point, 0)
+    }
+}
+
+impl Spawner {
+    pub fn new(prefab : PrefabId, schedule : Schedule, area : SpawnArea, seed : u64) -> Self {
+        Self {
+            prefab,
+            schedule,
+            area,
+            max_live : None,
+            velocity_spread : 0.0,
+            rng : Stream::new(seed),
+            elapsed : 0.0,
+            owed : 0.0,
+            spawned : Vec::new(),
+            live : Vec::new(),
+        }
+    }
+
+    /// Starts the random picks over from seed.
+    pub fn reseed(&mut self, seed : u64) {
+        self.rng = Stream::new(seed);
+    }
+
+    pub(super) fn live(&self) -> &[EntityId] {
+        &self.live
+    }
+
+    /// Whether every wave was spawned in full. Never true for rates.
+    pub fn is_finished(&self) -> bool {
+        match &self.schedule {
+            Schedule::Rate(_) => false,
+            Schedule::Waves(waves) => waves.iter().enumerate().all(|(i, w)| self.spawned.get(i).copied().unwrap_or(0) >= w.count),
+        }
+    }
+
+    /// How many copies are due by now and not spawned yet, and which wave each is from.
+    fn due(&mut self, dt : f32) -> Vec<Option<usize>> {
+        match &self.schedule {
+            Schedule::Rate(rate) => {
+                self.owed += rate * dt;
+                let count = self.owed.floor();
+                self.owed -= count;
+                vec![None; count as usize]
+            },
+            Schedule::Waves(waves) => {
+                self.spawned.resize(waves.len(), 0);
+                let mut due = Vec::new();
+                for (i, wave) in waves.iter().enumerate() {
+                    if self.elapsed < wave.start {
+                        continue;
+                    }
+                    let released = if wave.interval > 0.0 {
+                        (((self.elapsed - wave.start) / wave.interval).floor() as u32 + 1).min(wave.count)
+                    } else {
+                        wave.count
+                    };
+                    due.extend((self.spawned[i]..released).map(|_| Some(i)));
+                }
+                due
+            },
+        }
+    }
+
+    pub(super) fn digest(&self, sum : &mut Checksum) {
+        sum.u64(self.rng.state());
+        sum.f32(self.elapsed);
+        sum.f32(self.owed);
+        sum.usize(self.spawned.len());
+        for count in &self.spawned {
+            sum.u32(*count);
+        }
+        sum.usize(self.live.len());
+        for id in &self.live {
+            sum.u32(*id as u32);
+        }
+    }
+
+    fn pick(&mut self) -> glm::Vec2 {
+        match &self.area {
+            SpawnArea::Point => glm::Vec2::zeros(),
+            SpawnArea::Rect { half_width, half_height } => glm::vec2(
+                (self.rng.gen::<f32>() * 2.0 - 1.0) * half_width,
+                (self.rng.gen::<f32>() * 2.0 - 1.0) * half_height,
+            ),
+            SpawnArea::Circle { radius } => {
+                // Square root, so the middle isn't more crowded than the rim
+                let r = radius * self.rng.gen::<f32>().sqrt();
+                let angle = self.rng.gen::<f32>() * std::f32::consts::TAU;
+                glm::vec2(angle.cos(), angle.sin()) * r
+            },
+            SpawnArea::Path(points) => {
+                let length : f32 = points.windows(2).map(|x| (x[1] - x[0]).norm()).sum();
+                let mut along = self.rng.gen::<f32>() * length;
+                for leg in points.windows(2) {
+                    let leg_length = (leg[1] - leg[0]).norm();
+                    if along <= leg_length && leg_length > 0.0 {
+                        return leg[0] + (leg[1] - leg[0]) * (along / leg_length);
+                    }
+                    along -= leg_length;
+                }
+                points.last().copied().unwrap_or_else(glm::Vec2::zeros)
+            },
+        }
+    }
+}
+
+impl Game {
+    /// Spawns what every spawner has due, as far as their max_live allows.
+    pub(super) fn run_spawners(&mut self) {
+        let dt = self.physics.time_step;
+        let spawners : Vec<EntityId> = self.entities.iter()
+            .filter(|x| x.components.contains(CompFlag::Pos | CompFlag::Spw))
+            .map(|x| x.id)
+            .collect();
+        for id in spawners {
+            let i = id as usize;
+            let spawned_by = &self.spawned_by;
+            self.spawners[i].live.retain(|x| spawned_by[*x as usize] == Some(id));
+            let due = self.spawners[i].due(dt);
+            self.spawners[i].elapsed += dt;
+            for wave in due {
+                let spawner = &mut self.spawners[i];
+                if spawner.max_live.is_some_and(|max| spawner.live.len() >= max) {
+                    break;
+                }
+                let offset = spawner.pick();
+                let spread = spawner.velocity_spread;
+                let jitter = glm::vec2(spawner.rng.gen::<f32>() * 2.0 - 1.0, spawner.rng.gen::<f32>() * 2.0 - 1.0) * spread;
+                let prefab = spawner.prefab;
+                if let Some(wave) = wave {
+                    spawner.spawned[wave] += 1;
+                }
+                let position = Position { x : self.positions[i].x + offset.x, y : self.positions[i].y + offset.y };
+                let copy = self.spawn_prefab(prefab, &position);
+                self.velocities[copy as usize].x += jitter.x;
+                self.velocities[copy as usize].y += jitter.y;
+                self.spawned_by[copy as usize] = Some(id);
+                self.spawners[i].live.push(copy);
+            }
+        }
+    }
+}
+
+impl Save for Spawner {
+    fn save(&self, w : &mut Writer) {
+        self.prefab.save(w);
+        self.schedule.save(w);
+        self.area.save(w);
+        (self.max_live, self.velocity_spread).save(w);
+        self.rng.save(w);
+        (self.elapsed, self.owed).save(w);
+        self.spawned.save(w);
+        self.live.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let prefab = Save::load(r)?;
+        let schedule = Save::load(r)?;
+        let area = Save::load(r)?;
+        let (max_live, velocity_spread) = Save::load(r)?;
+        let rng = Save::load(r)?;
+        let (elapsed, owed) = Save::load(r)?;
+        let spawned = Save::load(r)?;
+        let live = Save::load(r)?;
+        Ok(Self { prefab, schedule, area, max_live, velocity_spread, rng, elapsed, owed, spawned, live })
+    }
+}
@@ -0,0 +1,249 @@
+
+use std::sync::Arc;
+
+use super::{CompFlag, EntityId, Game};
+use super::snapshot::{Reader, Save, Writer};
+
+/// Index of a machine in Game::machines
+pub type MachineId = usize;
+/// Index of a state in its machine
+pub type StateId = usize;
+
+/// Decides from the game whether the entity takes a transition
+pub type Guard = Arc<dyn Fn(&Game, EntityId) -> bool + Send + Sync>;
+/// Run on the entity when it enters, stays in or leaves a state
+pub type Callback = Arc<dyn Fn(&mut Game, EntityId) + Send + Sync>;
+
+#[derive(Clone)]
+pub struct State {
+    pub name : String,
+    pub on_enter : Option<Callback>,
+    /// Every update spent in the state, after the transitions are checked
+    pub on_update : Option<Callback>,
+    pub on_exit : Option<Callback>,
+}
+
+#[derive(Clone)]
+pub struct Transition {
+    pub from : StateId,
+    pub to : StateId,
+    pub guard : Guard,
+}
+
+/// States and the transitions between them, shared by every entity running the machine. Entities
+/// start out in the first state added.
+#[derive(Clone)]
+pub struct Machine {
+    pub name : String,
+    states : Vec<State>,
+    /// Checked in the order they were added, the first one allowed is taken
+    transitions : Vec<Transition>,
+}
+
+impl Machine {
+    pub fn new(name : &str) -> Self {
+        Self { name : String::from(name), states : Vec::new(), transitions : Vec::new() }
+    }
+
+    pub fn add_state(&mut self, name : &str) -> StateId {
+        self.states.push(State { name : String::from(name), on_enter : None, on_update : None, on_exit : None });
+        self.states.len() - 1
+    }
+
+    pub fn on_enter(&mut self, state : StateId, callback : impl Fn(&mut Game, EntityId) + Send + Sync + 'static) {
+        self.states[state].on_enter = Some(Arc::new(callback));
+    }
+
+    pub fn on_update(&mut self, state : StateId, callback : impl Fn(&mut Game, EntityId) + Send + Sync + 'static) {
+        self.states[state].on_update = Some(Arc::new(callback));
+    }
+
+    pub fn on_exit(&mut self, state : StateId, callback : impl Fn(&mut Game, EntityId) + Send + Sync + 'static) {
+        self.states[state].on_exit = Some(Arc::new(callback));
+    }
+
+    pub fn add_transition(&mut self, from : StateId, to : StateId, guard : impl Fn(&Game, EntityId) -> bool + Send + Sync + 'static) {
+        self.transitions.push(Transition { from, to, guard : Arc::new(guard) });
+    }
+
+    pub fn states(&self) -> &[State] {
+        &self.states
+    }
+
+    pub fn transitions(&self) -> &[Transition] {
+        &self.transitions
+    }
+}
+
+/// Runs a machine from Game::machines on its entity.
+#[derive(Debug, Clone, Default)]
+pub struct StateMachine {
+    pub machine : MachineId,
+    /// None until the first update enters the starting state
+    current : Option<StateId>,
+    /// Seconds since the current state was entered
+    time_in_state : f32,
+}
+
+impl StateMachine {
+    pub fn new(machine : MachineId) -> Self {
+        Self { machine, current : None, time_in_state : 0.0 }
+    }
+
+    pub fn current(&self) -> Option<StateId> {
+        self.current
+    }
+
+    pub fn time_in_state(&self) -> f32 {
+        self.time_in_state
+    }
+}
+
+impl Game {
+    pub fn add_machine(&mut self, machine : Machine) -> MachineId {
+        self.machines.push(machine);
+        self.machines.len() - 1
+    }
+
+    /// Name of the state the entity's machine is in, if it has started.
+    pub fn state_name(&self, id : EntityId) -> Option<&str> {
+        let fsm = &self.state_machines[id as usize];
+        fsm.current.map(|state| self.machines[fsm.machine].states[state].name.as_str())
+    }
+
+    /// One line per entity running a machine, like "12: guard/chase for 1.25s".
+    pub fn describe_states(&self) -> String {
+        self.entities.iter()
+            .filter(|x| x.components.contains(CompFlag::Fsm))
+            .map(|x| {
+                let fsm = &self.state_machines[x.id as usize];
+                let machine = &self.machines[fsm.machine];
+                let state = fsm.current.map_or("(not started)", |s| machine.states[s].name.as_str());
+                format!("{}: {}/{} for {:.2}s\n", x.id, machine.name, state, fsm.time_in_state)
+            })
+            .collect()
+    }
+
+    /// Enters the starting state of machines that haven't started, takes at most one transition
+    /// per entity, then runs the on_update of the state it's in.
+    pub(super) fn update_state_machines(&mut self) {
+        let dt = self.physics.time_step;
+        let running : Vec<EntityId> = self.entities.iter()
+            .filter(|x| x.components.contains(CompFlag::Fsm))
+            .map(|x| x.id)
+            .collect();
+        for id in running {
+            let i = id as usize;
+            // Despawned or stopped by something earlier in this update
+            if !self.is_running(id) {
+                continue;
+            }
+            let machine = self.state_machines[i].machine;
+            let current = match self.state_machines[i].current {
+                Some(state) => state,
+                None => {
+                    if self.machines[machine].states.is_empty() {
+                        continue;
+                    }
+                    self.enter_state(id, 0);
+                    if !self.is_running(id) {
+                        continue;
+                    }
+                    0
+                },
+            };
+            let next = self.machines[machine].transitions.iter()
+                .filter(|x| x.from == current)
+                .find(|x| (x.guard)(self, id))
+                .map(|x| x.to);
+            if let Some(next) = next {
+                if let Some(callback) = self.machines[machine].states[current].on_exit.clone() {
+                    callback(self, id);
+                    if !self.is_running(id) {
+                        continue;
+                    }
+                }
+                self.enter_state(id, next);
+                if !self.is_running(id) {
+                    continue;
+                }
+            }
+            let state = self.state_machines[i].current.unwrap();
+            if let Some(callback) = self.machines[machine].states[state].on_update.clone() {
+                callback(self, id);
+                if !self.is_running(id) {
+                    continue;
+                }
+            }
+            self.state_machines[i].time_in_state += dt;
+        }
+    }
+
+    /// Callbacks can despawn their entity or take its Fsm away, which stops the machine there.
+    fn is_running(&self, id : EntityId) -> bool {
+        self.entities[id as usize].components.contains(CompFlag::Fsm)
+    }
+
+    fn enter_state(&mut self, id : EntityId, state : StateId) {
+        let fsm = &mut self.state_machines[id as usize];
+        fsm.current = Some(state);
+        fsm.time_in_state = 0.0;
+        if let Some(callback) = self.machines[fsm.machine].states[state].on_enter.clone() {
+            callback(self, id);
+        }
+    }
+}
+
+impl Save for StateMachine {
+    fn save(&self, w : &mut Writer) {
+        (self.machine, self.current, self.time_in_state).save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (machine, current, time_in_state) = Save::load(r)?;
+        Ok(Self { machine, current, time_in_state })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn callbacks_that_stop_their_machine_end_its_update() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut machine = Machine::new("doomed");
+        let (alive, dead) = (machine.add_state("alive"), machine.add_state("dead"));
+        machine.add_transition(alive, dead, |_, _| true);
+        machine.on_exit(alive, |game, id| game.despawn(id));
+        for state in [alive, dead] {
+            let count = calls.clone();
+            machine.on_update(state, move |_, _| { count.fetch_add(1, Ordering::SeqCst); });
+        }
+        let count = calls.clone();
+        machine.on_enter(dead, move |_, _| { count.fetch_add(1, Ordering::SeqCst); });
+
+        let mut stopping = Machine::new("stopping");
+        let start = stopping.add_state("start");
+        stopping.on_enter(start, |game, id| game.entities[id as usize].components.remove(CompFlag::Fsm));
+        let count = calls.clone();
+        stopping.on_update(start, move |_, _| { count.fetch_add(1, Ordering::SeqCst); });
+
+        let mut game = Game::new();
+        let (doomed, stopping) = (game.add_machine(machine), game.add_machine(stopping));
+        let a = game.add_entity(CompFlag::Fsm);
+        game.state_machines[a as usize] = StateMachine::new(doomed);
+        game.state_machines[a as usize].current = Some(alive);
+        let b = game.add_entity(CompFlag::Fsm);
+        game.state_machines[b as usize] = StateMachine::new(stopping);
+
+        let (mut tx, _rx) = std::sync::mpsc::sync_channel(1);
+        game.update(&mut tx);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+        assert!(game.entities[a as usize].components.is_empty());
+        assert_eq!(game.state_machines[b as usize].current, Some(start));
+        assert_eq!(game.state_machines[b as usize].time_in_state, 0.0);
+    }
+}
@@ -0,0 +1,355 @@
+
+use rand::Rng;
+
+use super::{Checksum, CompFlag, EntityId, Game, Position, Randomness, Stream, Velocity};
+use super::snapshot::{Reader, Save, Writer};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Target {
+    Point(glm::Vec2),
+    /// Followed wherever it goes. Ignored once it no longer has a position, and dropped along
+    /// with its behaviour when the entity is despawned.
+    Entity(EntityId),
+}
+
+#[derive(Debug, Clone)]
+pub enum Behaviour {
+    /// Heads straight for the target at full speed
+    Seek(Target),
+    /// Heads straight away from the target when it's within radius
+    Flee { target : Target, radius : f32 },
+    /// Seeks, but slows down within slowing_radius to stop on the target
+    Arrive { target : Target, slowing_radius : f32 },
+    /// Seeks a point drifting around a circle of the given radius, held distance ahead of the
+    /// agent. The point moves by up to jitter radians per second.
+    Wander { distance : f32, radius : f32, jitter : f32 },
+    /// Seeks where the entity will be by the time the agent could reach it
+    Pursue(EntityId),
+    /// Swerves around colliders up to look_ahead in front of the agent
+    AvoidObstacles { look_ahead : f32 },
+    /// Heads where the navigation's flow field with this index points
+    FollowFlow(usize),
+    /// Seeks every waypoint in turn, moving on once it's within reach, and arrives at the last one.
+    /// Takes paths from find_path or path requests. With a nav grid, an agent pushed out of
+    /// sight of its waypoint heads back to an earlier one it can see.
+    FollowPath { waypoints : Vec<glm::Vec2>, reach : f32, slowing_radius : f32 },
+}
+
+impl Behaviour {
+    /// The entity the behaviour follows, if it follows one.
+    pub fn entity(&self) -> Option<EntityId> {
+        match self {
+            Behaviour::Seek(Target::Entity(id))
+            | Behaviour::Flee { target : Target::Entity(id), .. }
+            | Behaviour::Arrive { target : Target::Entity(id), .. }
+            | Behaviour::Pursue(id) => Some(*id),
+            _ => None,
+        }
+    }
+}
+
+/// Reynolds style steering for AI agents. Every behaviour asks for a force, which are summed by
+/// weight and capped by max_force, and the velocity is capped by max_speed.
+#[derive(Debug, Clone)]
+pub struct Steering {
+    pub behaviours : Vec<(Behaviour, f32)>,
+    pub max_force : f32,
+    pub max_speed : f32,
+    /// Where on its circle the wander point is
+    wander_angle : f32,
+    /// Picks the wander jitter. Taken from the world's randomness for the entity on first use,
+    /// unless reseeded before then.
+    rng : Option<Stream>,
+    /// Which waypoint FollowPath is heading for
+    waypoint : usize,
+}
+
+impl Default for Steering {
+    fn default() -> Self {
+        Self::new(1.0, 0.5)
+    }
+}
+
+impl Steering {
+    pub fn new(max_force : f32, max_speed : f32) -> Self {
+        Self { behaviours : Vec::new(), max_force, max_speed, wander_angle : 0.0, rng : None, waypoint : 0 }
+    }
+
+    pub fn with(mut self, behaviour : Behaviour, weight : f32) -> Self {
+        self.behaviours.push((behaviour, weight));
+        self
+    }
+
+    /// Starts FollowPath over from the first waypoint, for after its path was swapped out.
+    pub fn restart_path(&mut self) {
+        self.waypoint = 0;
+    }
+
+    /// Drops the behaviours following the given entity, so they don't chase whatever reuses its id.
+    pub(super) fn forget(&mut self, id : EntityId) {
+        self.behaviours.retain(|(behaviour, _)| behaviour.entity() != Some(id));
+    }
+
+    /// Starts the random picks over from seed.
+    pub fn reseed(&mut self, seed : u64) {
+        self.rng = Some(Stream::new(seed));
+    }
+
+    /// Between -1 and 1.
+    fn jitter(&mut self, random : &Randomness, id : EntityId) -> f32 {
+        let rng = self.rng.get_or_insert_with(|| random.entity_stream("wander", id));
+        rng.gen::<f32>() * 2.0 - 1.0
+    }
+
+    pub(super) fn digest(&self, sum : &mut Checksum) {
+        sum.f32(self.wander_angle);
+        sum.option(self.rng.as_ref().map(|x| x.state()));
+        sum.usize(self.waypoint);
+    }
+}
+
+impl Game {
+    fn target_position(&self, target : &Target) -> Option<glm::Vec2> {
+        match target {
+            Target::Point(p) => Some(*p),
+            Target::Entity(id) => {
+                if !self.entities[*id as usize].components.contains(CompFlag::Pos) {
+                    return None;
+                }
+                let pos = &self.positions[*id as usize];
+                Some(glm::vec2(pos.x, pos.y))
+            },
+        }
+    }
+
+    /// Force turning the velocity v into one heading for target at the given speed.
+    fn seek_force(p : &glm::Vec2, v : &glm::Vec2, target : &glm::Vec2, speed : f32) -> glm::Vec2 {
+        let d = target - p;
+        let distance = d.norm();
+        if distance == 0.0 {
+            return -v;
+        }
+        d / distance * speed - v
+    }
+
+    /// The force one behaviour asks for.
+    fn behaviour_force(&self, id : EntityId, steering : &mut Steering, behaviour : &Behaviour) -> glm::Vec2 {
+        let pos = &self.positions[id as usize];
+        let vel = &self.velocities[id as usize];
+        let (p, v) = (glm::vec2(pos.x, pos.y), glm::vec2(vel.x, vel.y));
+        let max_speed = steering.max_speed;
+        match behaviour {
+            Behaviour::Seek(target) => match self.target_position(target) {
+                Some(t) => Self::seek_force(&p, &v, &t, max_speed),
+                None => glm::Vec2::zeros(),
+            },
+            Behaviour::Flee { target, radius } => match self.target_position(target) {
+                // Seeking the point mirrored through the agent leads straight away from the target
+                Some(t) if (p - t).norm() < *radius => Self::seek_force(&p, &v, &(p * 2.0 - t), max_speed),
+                _ => glm::Vec2::zeros(),
+            },
+            Behaviour::Arrive { target, slowing_radius } => match self.target_position(target) {
+                Some(t) => Self::arrive_force(&p, &v, &t, *slowing_radius, max_speed),
+                None => glm::Vec2::zeros(),
+            },
+            Behaviour::Wander { distance, radius, jitter } => {
+                steering.wander_angle += steering.jitter(&self.random, id) * jitter * self.physics.time_step;
+                let heading = if v.norm_squared() > 0.0 { v.normalize() } else { glm::vec2(1.0, 0.0) };
+                let angle = steering.wander_angle;
+                let point = p + heading * *distance + glm::vec2(angle.cos(), angle.sin()) * *radius;
+                Self::seek_force(&p, &v, &point, max_speed)
+            },
+            Behaviour::Pursue(other) => match self.target_position(&Target::Entity(*other)) {
+                Some(t) => {
+                    let tv = &self.velocities[*other as usize];
+                    let lead = if max_speed > 0.0 { (t - p).norm() / max_speed } else { 0.0 };
+                    Self::seek_force(&p, &v, &(t + glm::vec2(tv.x, tv.y) * lead), max_speed)
+                },
+                None => glm::Vec2::zeros(),
+            },
+            Behaviour::AvoidObstacles { look_ahead } => self.avoidance_force(id, &p, &v, *look_ahead, max_speed),
+            Behaviour::FollowFlow(field) => match self.navigation.as_ref().and_then(|n| n.flow_fields.get(*field)) {
+                Some(f) => f.direction(pos) * max_speed - v,
+                None => glm::Vec2::zeros(),
+            },
+            Behaviour::FollowPath { waypoints, reach, slowing_radius } => {
+                if waypoints.is_empty() {
+                    return glm::Vec2::zeros();
+                }
+                // The path may have been swapped for a shorter one without restart_path
+                steering.waypoint = steering.waypoint.min(waypoints.len() - 1);
+                while steering.waypoint + 1 < waypoints.len() && (waypoints[steering.waypoint] - p).norm() < *reach {
+                    steering.waypoint += 1;
+                }
+                // Knocked off the path, so back up to the latest waypoint in sight, if there's one
+                if let Some(grid) = self.navigation.as_ref().map(|n| &n.grid) {
+                    if !grid.line_of_sight(&p, &waypoints[steering.waypoint]) {
+                        if let Some(i) = (0..steering.waypoint).rev().find(|i| grid.line_of_sight(&p, &waypoints[*i])) {
+                            steering.waypoint = i;
+                        }
+                    }
+                }
+                let t = &waypoints[steering.waypoint];
+                if steering.waypoint + 1 == waypoints.len() {
+                    Self::arrive_force(&p, &v, t, *slowing_radius, max_speed)
+                } else {
+                    Self::seek_force(&p, &v, t, max_speed)
+                }
+            },
+        }
+    }
+
+    /// Seeks, slowing down within slowing_radius to stop on the target.
+    fn arrive_force(p : &glm::Vec2, v : &glm::Vec2, target : &glm::Vec2, slowing_radius : f32, max_speed : f32) -> glm::Vec2 {
+        let distance = (target - p).norm();
+        let speed = if distance < slowing_radius { max_speed * distance / slowing_radius } else { max_speed };
+        Self::seek_force(p, v, target, speed)
+    }
+
+    /// Finds the closest collider whose bounding circle lies across the path ahead, and pushes
+    /// sideways away from it, harder the closer it is.
+    fn avoidance_force(&self, id : EntityId, p : &glm::Vec2, v : &glm::Vec2, look_ahead : f32, max_speed : f32) -> glm::Vec2 {
+        let speed = v.norm();
+        if speed == 0.0 {
+            return glm::Vec2::zeros();
+        }
+        let heading = v / speed;
+        let side = glm::vec2(-heading.y, heading.x);
+        let own_radius = if self.entities[id as usize].components.contains(CompFlag::Col) {
+            let (hw, hh) = self.colliders[id as usize].half_extents();
+            hw.max(hh)
+        } else {
+            0.0
+        };
+
+        // Cells reaching out half the look ahead from the middle of the path cover all of it
+        let centre = p + heading * look_ahead / 2.0;
+        let middle = Position { x : centre.x, y : centre.y };
+        let mut closest : Option<(f32, f32)> = None;
+        let nearby = self.spacially_sorted.find_within(&middle, look_ahead / 2.0 + own_radius)
+            .into_iter()
+            .chain(self.static_sorted.find_within(&middle, look_ahead / 2.0 + own_radius));
+        for other in nearby {
+            let components = self.entities[other as usize].components;
+            if other == id || !components.contains(CompFlag::Pos | CompFlag::Col) || components.contains(CompFlag::Sen) {
+                continue;
+            }
+            let (hw, hh) = self.colliders[other as usize].half_extents();
+            let reach = hw.max(hh) + own_radius;
+            let o = &self.positions[other as usize];
+            let d = glm::vec2(o.x, o.y) - p;
+            let (ahead, across) = (d.dot(&heading), d.dot(&side));
+            if ahead <= 0.0 || ahead - reach > look_ahead || across.abs() >= reach {
+                continue;
+            }
+            if closest.is_none_or(|c| ahead < c.0) {
+                closest = Some((ahead, across));
+            }
+        }
+
+        match closest {
+            Some((ahead, across)) => {
+                let urgency = 1.0 - (ahead / look_ahead).min(1.0);
+                let away = if across > 0.0 { -side } else { side };
+                away * max_speed * (0.5 + urgency)
+            },
+            None => glm::Vec2::zeros(),
+        }
+    }
+
+    /// Blends every steered entity's behaviours and applies them to its velocity. All forces are
+    /// worked out from the velocities as they were before any of them changed.
+    pub(super) fn steer(&mut self) {
+        let dt = self.physics.time_step;
+        let steered : Vec<EntityId> = self.entities.iter()
+            .filter(|x| x.components.contains(CompFlag::Pos | CompFlag::Vel | CompFlag::Str) && !x.components.intersects(CompFlag::Sta | CompFlag::Slp))
+            .map(|x| x.id)
+            .collect();
+
+        let mut velocities : Vec<(EntityId, Velocity)> = Vec::with_capacity(steered.len());
+        for id in steered {
+            // Taken out for the duration, so wandering can update it while the game is borrowed
+            let mut steering = std::mem::take(&mut self.steerings[id as usize]);
+            let behaviours = std::mem::take(&mut steering.behaviours);
+            let mut force = glm::Vec2::zeros();
+            for (behaviour, weight) in behaviours.iter() {
+                force += self.behaviour_force(id, &mut steering, behaviour) * *weight;
+            }
+            steering.behaviours = behaviours;
+            if force.norm() > steering.max_force {
+                force = force.normalize() * steering.max_force;
+            }
+            let vel = &self.velocities[id as usize];
+            let mut v = glm::vec2(vel.x, vel.y) + force * self.inverse_mass(id) * dt;
+            if v.norm() > steering.max_speed {
+                v = v.normalize() * steering.max_speed;
+            }
+            velocities.push((id, Velocity { x : v.x, y : v.y }));
+            self.steerings[id as usize] = steering;
+        }
+        for (id, vel) in velocities {
+            self.velocities[id as usize] = vel;
+        }
+    }
+}
+
+impl Save for Steering {
+    fn save(&self, w : &mut Writer) {
+        self.behaviours.save(w);
+        (self.max_force, self.max_speed, self.wander_angle).save(w);
+        self.rng.save(w);
+        self.waypoint.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let behaviours = Save::load(r)?;
+        let (max_force, max_speed, wander_angle) = Save::load(r)?;
+        let rng = Save::load(r)?;
+        let waypoint = Save::load(r)?;
+        Ok(Self { behaviours, max_force, max_speed, wander_angle, rng, waypoint })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{NavGrid, Navigation};
+    use super::*;
+
+    #[test]
+    fn despawning_a_target_drops_the_behaviours_following_it() {
+        let mut game = Game::new();
+        let prey = game.add_entity(CompFlag::Pos | CompFlag::Vel);
+        let hunter = game.add_entity(CompFlag::Pos | CompFlag::Vel | CompFlag::Str);
+        game.positions[hunter as usize] = Position { x : 0.5, y : 0.5 };
+        game.steerings[hunter as usize] = Steering::new(1.0, 0.5)
+            .with(Behaviour::Pursue(prey), 1.0)
+            .with(Behaviour::Arrive { target : Target::Entity(prey), slowing_radius : 0.1 }, 1.0)
+            .with(Behaviour::Seek(Target::Point(glm::vec2(0.0, 0.0))), 1.0);
+
+        game.despawn(prey);
+        assert_eq!(game.add_entity(CompFlag::Pos), prey);
+        let behaviours = &game.steerings[hunter as usize].behaviours;
+        assert_eq!(behaviours.len(), 1);
+        assert!(matches!(behaviours[0].0, Behaviour::Seek(Target::Point(_))));
+    }
+
+    #[test]
+    fn follow_path_survives_its_path_shrinking_under_it() {
+        let mut game = Game::new();
+        game.navigation = Some(Navigation::new(NavGrid::new(glm::vec2(-1.0, -1.0), 0.1, 20, 20)));
+        let agent = game.add_entity(CompFlag::Pos | CompFlag::Vel | CompFlag::Str);
+        let waypoints = vec![glm::vec2(0.0, 0.0), glm::vec2(0.01, 0.0), glm::vec2(0.5, 0.0)];
+        game.steerings[agent as usize] = Steering::new(1.0, 0.5)
+            .with(Behaviour::FollowPath { waypoints, reach : 0.05, slowing_radius : 0.1 }, 1.0);
+
+        let (mut tx, _rx) = std::sync::mpsc::sync_channel(1);
+        game.update(&mut tx);
+        assert_eq!(game.steerings[agent as usize].waypoint, 2);
+
+        for waypoints in [vec![glm::vec2(-0.5, 0.0)], Vec::new()] {
+            game.steerings[agent as usize].behaviours[0].0 = Behaviour::FollowPath { waypoints, reach : 0.05, slowing_radius : 0.1 };
+            game.update(&mut tx);
+        }
+        assert!(game.velocities[agent as usize].x < 0.0, "Should have headed for the new path");
+    }
+}
@@ -0,0 +1,300 @@
+
+use super::{Collider, CompFlag, EntityId, Game, Position, Shape};
+use super::snapshot::{Reader, Save, Writer};
+
+/// Which tile of the tileset a cell of the map shows. 0 is empty, n > 0 is the nth cell of the
+/// tileset, counted row by row from the top left.
+pub type TileId = u16;
+
+pub const EMPTY_TILE : TileId = 0;
+
+/// Side length, in tiles, of the square chunks the map is rendered in
+pub const CHUNK_SIZE : usize = 16;
+
+/// The texture tiles are cut from, and which of them block movement.
+#[derive(Debug, Clone)]
+pub struct Tileset {
+    /// Name of the texture in the texture manager, like "/tiles.png"
+    pub texture : String,
+    pub columns : u32,
+    pub rows : u32,
+    /// Tiles listed here get a collider where they border on open space
+    pub blocking : Vec<TileId>,
+    /// Layers, restitution and friction of the tile colliders. The shape is replaced by the tile's box.
+    pub surface : Collider,
+}
+
+impl Tileset {
+    pub fn new(texture : &str, columns : u32, rows : u32) -> Self {
+        Self {
+            texture : String::from(texture),
+            columns,
+            rows,
+            blocking : Vec::new(),
+            surface : Collider { restitution : 0.0, friction : 0.5, ..Collider::default() },
+        }
+    }
+
+    pub fn is_blocking(&self, tile : TileId) -> bool {
+        tile != EMPTY_TILE && self.blocking.contains(&tile)
+    }
+}
+
+/// One chunk of the tilemap as the renderer gets it, sent whenever a tile in it changes.
+#[derive(Debug, Clone)]
+pub struct TileChunk {
+    /// Chunks are numbered row by row from the bottom left of the map
+    pub index : usize,
+    /// Bottom left corner of the chunk's first tile
+    pub origin : Position,
+    pub tile_size : f32,
+    /// Tiles across. The last chunks in a row or column can be cut short by the edge of the map.
+    pub width : usize,
+    /// Row by row from the bottom
+    pub tiles : Vec<TileId>,
+    pub texture : String,
+    pub columns : u32,
+    pub rows : u32,
+}
+
+/// A grid of tiles with its bottom left corner at origin, rows counted upwards.
+#[derive(Debug, Clone)]
+pub struct Tilemap {
+    pub origin : glm::Vec2,
+    pub tile_size : f32,
+    width : usize,
+    height : usize,
+    tiles : Vec<TileId>,
+    tileset : Tileset,
+    /// Static entity colliding for each tile that has one
+    colliders : Vec<Option<EntityId>>,
+    /// Chunks the renderer hasn't seen since they last changed
+    dirty : Vec<bool>,
+}
+
+impl Tilemap {
+    /// An empty map
+    pub fn new(width : usize, height : usize, tile_size : f32, origin : glm::Vec2, tileset : Tileset) -> Self {
+        let mut tilemap = Self {
+            origin,
+            tile_size,
+            width,
+            height,
+            tiles : vec![EMPTY_TILE; width * height],
+            tileset,
+            colliders : vec![None; width * height],
+            dirty : Vec::new(),
+        };
+        tilemap.dirty = vec![true; tilemap.chunks_across() * tilemap.chunks_up()];
+        tilemap
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// The static entities colliding for tiles.
+    pub(super) fn collider_ids(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.colliders.iter().flatten().copied()
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn tileset(&self) -> &Tileset {
+        &self.tileset
+    }
+
+    fn chunks_across(&self) -> usize {
+        self.width.div_ceil(CHUNK_SIZE)
+    }
+
+    fn chunks_up(&self) -> usize {
+        self.height.div_ceil(CHUNK_SIZE)
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.dirty.len()
+    }
+
+    /// The tile at column x and row y, or None outside the map.
+    pub fn tile(&self, x : usize, y : usize) -> Option<TileId> {
+        if x < self.width && y < self.height { Some(self.tiles[y * self.width + x]) } else { None }
+    }
+
+    /// Sets a tile of a map the game doesn't hold yet. Once it does, go through Game::set_tile,
+    /// which keeps the colliders up to date.
+    pub fn set(&mut self, x : usize, y : usize, tile : TileId) {
+        assert!(x < self.width && y < self.height, "Tile ({}, {}) is outside the {}x{} tilemap!", x, y, self.width, self.height);
+        self.tiles[y * self.width + x] = tile;
+        let chunk = (y / CHUNK_SIZE) * self.chunks_across() + x / CHUNK_SIZE;
+        self.dirty[chunk] = true;
+    }
+
+    /// Whether the tile is in the map and blocks movement.
+    pub fn is_blocking(&self, x : usize, y : usize) -> bool {
+        self.tile(x, y).is_some_and(|t| self.tileset.is_blocking(t))
+    }
+
+    /// Column and row of the tile covering position, or None outside the map.
+    pub fn tile_at(&self, position : &Position) -> Option<(usize, usize)> {
+        let x = ((position.x - self.origin.x) / self.tile_size).floor();
+        let y = ((position.y - self.origin.y) / self.tile_size).floor();
+        if x < 0.0 || y < 0.0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+
+    pub fn tile_centre(&self, x : usize, y : usize) -> glm::Vec2 {
+        self.origin + glm::vec2(x as f32 + 0.5, y as f32 + 0.5) * self.tile_size
+    }
+
+    /// A blocking tile only needs a collider if something can reach it, so tiles walled in on
+    /// every side by other blocking ones go without. Anything past the edge of the map counts as open.
+    fn needs_collider(&self, x : usize, y : usize) -> bool {
+        if !self.is_blocking(x, y) {
+            return false;
+        }
+        let open = |dx : isize, dy : isize| {
+            let (nx, ny) = (x as isize + dx, y as isize + dy);
+            nx < 0 || ny < 0 || !self.is_blocking(nx as usize, ny as usize)
+        };
+        open(-1, 0) || open(1, 0) || open(0, -1) || open(0, 1)
+    }
+
+    fn chunk(&self, index : usize) -> TileChunk {
+        let (cx, cy) = (index % self.chunks_across() * CHUNK_SIZE, index / self.chunks_across() * CHUNK_SIZE);
+        let width = CHUNK_SIZE.min(self.width - cx);
+        let height = CHUNK_SIZE.min(self.height - cy);
+        let corner = self.origin + glm::vec2(cx as f32, cy as f32) * self.tile_size;
+        TileChunk {
+            index,
+            origin : Position { x : corner.x, y : corner.y },
+            tile_size : self.tile_size,
+            width,
+            tiles : (cy..cy + height).flat_map(|y| self.tiles[y * self.width + cx..y * self.width + cx + width].iter().copied()).collect(),
+            texture : self.tileset.texture.clone(),
+            columns : self.tileset.columns,
+            rows : self.tileset.rows,
+        }
+    }
+
+    /// Every chunk that changed since the last call.
+    pub(super) fn take_dirty_chunks(&mut self) -> Vec<TileChunk> {
+        let dirty : Vec<usize> = (0..self.dirty.len()).filter(|i| self.dirty[*i]).collect();
+        for i in dirty.iter() {
+            self.dirty[*i] = false;
+        }
+        dirty.into_iter().map(|i| self.chunk(i)).collect()
+    }
+
+    /// For chunks that never made it to the renderer.
+    pub(super) fn mark_dirty(&mut self, index : usize) {
+        if index < self.dirty.len() {
+            self.dirty[index] = true;
+        }
+    }
+}
+
+impl Game {
+    pub fn tilemap(&self) -> Option<&Tilemap> {
+        self.tilemap.as_ref()
+    }
+
+    /// Replaces the tilemap, despawning the colliders of the old one and spawning static box
+    /// colliders for the blocking tiles of the new one. The grid broadphase only looks at
+    /// neighbouring cells, so tiles shouldn't be larger than a cell.
+    pub fn set_tilemap(&mut self, tilemap : Option<Tilemap>) {
+        if let Some(old) = self.tilemap.take() {
+            for id in old.colliders.iter().flatten() {
+                self.despawn(*id);
+            }
+        }
+        self.tilemap = tilemap;
+        let (width, height) = match &mut self.tilemap {
+            // A map taken from another game still names that game's colliders
+            Some(t) => {
+                t.colliders = vec![None; t.width * t.height];
+                (t.width, t.height)
+            },
+            None => return,
+        };
+        for y in 0..height {
+            for x in 0..width {
+                self.update_tile_collider(x, y);
+            }
+        }
+    }
+
+    /// Changes one tile, and adds or removes colliders around it so physics follows along.
+    pub fn set_tile(&mut self, x : usize, y : usize, tile : TileId) {
+        let tilemap = match &mut self.tilemap {
+            Some(t) => t,
+            None => return,
+        };
+        tilemap.set(x, y, tile);
+        let (width, height) = (tilemap.width, tilemap.height);
+        // Neighbours might have been walled in or opened up
+        for ny in y.saturating_sub(1)..(y + 2).min(height) {
+            for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                self.update_tile_collider(nx, ny);
+            }
+        }
+    }
+
+    /// Spawns or despawns the tile's collider depending on whether it needs one.
+    fn update_tile_collider(&mut self, x : usize, y : usize) {
+        let tilemap = self.tilemap.as_ref().unwrap();
+        let i = y * tilemap.width + x;
+        let existing = tilemap.colliders[i];
+        if !tilemap.needs_collider(x, y) {
+            if let Some(id) = existing {
+                self.despawn(id);
+                self.tilemap.as_mut().unwrap().colliders[i] = None;
+            }
+            return;
+        }
+        if existing.is_some() {
+            return;
+        }
+        let centre = tilemap.tile_centre(x, y);
+        let half = tilemap.tile_size / 2.0;
+        let collider = Collider {
+            shape : Shape::Aabb { half_width : half, half_height : half },
+            ..tilemap.tileset.surface.clone()
+        };
+        let id = self.add_entity(CompFlag::Pos | CompFlag::Col | CompFlag::Sta);
+        self.positions[id as usize] = Position { x : centre.x, y : centre.y };
+        self.colliders[id as usize] = collider;
+        self.tilemap.as_mut().unwrap().colliders[i] = Some(id);
+    }
+}
+
+/// Every chunk comes back dirty, so the renderer gets the whole map again.
+impl Save for Tilemap {
+    fn save(&self, w : &mut Writer) {
+        (self.origin, self.tile_size).save(w);
+        (self.width, self.height).save(w);
+        self.tileset.save(w);
+        self.tiles.save(w);
+        self.colliders.save(w);
+    }
+
+    fn load(r : &mut Reader) -> Result<Self, String> {
+        let (origin, tile_size) = Save::load(r)?;
+        let (width, height) : (usize, usize) = Save::load(r)?;
+        let tileset = Save::load(r)?;
+        let tiles : Vec<TileId> = Save::load(r)?;
+        let colliders : Vec<Option<EntityId>> = Save::load(r)?;
+        // Checked against what was actually read before allocating anything from the sizes
+        if Some(tiles.len()) != width.checked_mul(height) || colliders.len() != tiles.len() {
+            return Err(format!("Snapshot has {} tiles and {} tile colliders for a {} by {} map", tiles.len(), colliders.len(), width, height));
+        }
+        let mut tilemap = Tilemap::new(width, height, tile_size, origin, tileset);
+        tilemap.tiles = tiles;
+        tilemap.colliders = colliders;
+        Ok(tilemap)
+    }
+}
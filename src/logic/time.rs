@@ -0,0 +1,68 @@
+
+/// Slowest and fastest the simulation can be made to run, relative to real time
+pub const MIN_TIME_SCALE : f32 = 0.1;
+pub const MAX_TIME_SCALE : f32 = 10.0;
+
+/// Most updates run to catch up at once. Time owed past this is dropped, so a simulation that
+/// can't keep up slows down instead of falling further and further behind.
+const MAX_CATCH_UP : u32 = 20;
+
+/// Decides how many updates the game loop runs for the real time that passed, so the simulation
+/// keeps pace with the clock at any time scale, stops while paused and can be stepped.
+#[derive(Debug, Clone)]
+pub struct TimeControl {
+    paused : bool,
+    scale : f32,
+    /// Updates asked for while paused
+    steps : u32,
+    /// Simulated seconds owed that didn't add up to a whole update yet
+    owed : f32,
+}
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        Self { paused : false, scale : 1.0, steps : 0, owed : 0.0 }
+    }
+}
+
+impl TimeControl {
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+        self.owed = 0.0;
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Clamped between MIN_TIME_SCALE and MAX_TIME_SCALE.
+    pub fn set_scale(&mut self, scale : f32) {
+        self.scale = scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+    }
+
+    /// Runs exactly one more update while paused. Does nothing while running.
+    pub fn step(&mut self) {
+        if self.paused {
+            self.steps += 1;
+        }
+    }
+
+    /// How many updates of time_step simulated seconds are due after real_seconds passed.
+    pub fn ticks(&mut self, real_seconds : f32, time_step : f32) -> u32 {
+        if self.paused {
+            return std::mem::take(&mut self.steps);
+        }
+        self.owed += real_seconds * self.scale;
+        let ticks = (self.owed / time_step).floor() as u32;
+        self.owed -= ticks as f32 * time_step;
+        if ticks > MAX_CATCH_UP {
+            self.owed = 0.0;
+            return MAX_CATCH_UP;
+        }
+        ticks
+    }
+}
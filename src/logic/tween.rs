@@ -0,0 +1,233 @@
+
+use std::f32::consts::PI;
+
+use super::{CompFlag, EntityId, Game};
+
+/// Maps how far along a tween is, 0 to 1, to how far along its value is. Elastic overshoots on the way.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Ease {
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    ElasticIn,
+    ElasticOut,
+    BounceIn,
+    BounceOut,
+}
+
+impl Ease {
+    pub fn apply(self, t : f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Ease::Linear => t,
+            Ease::QuadIn => t * t,
+            Ease::QuadOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Ease::QuadInOut => if t < 0.5 { 2.0 * t * t } else { 1.0 - 2.0 * (1.0 - t) * (1.0 - t) },
+            Ease::CubicIn => t * t * t,
+            Ease::CubicOut => 1.0 - (1.0 - t).powi(3),
+            Ease::CubicInOut => if t < 0.5 { 4.0 * t * t * t } else { 1.0 - 4.0 * (1.0 - t).powi(3) },
+            Ease::ElasticIn => 1.0 - Ease::ElasticOut.apply(1.0 - t),
+            Ease::ElasticOut => {
+                if t == 0.0 || t == 1.0 {
+                    return t;
+                }
+                2f32.powf(-10.0 * t) * ((t * 10.0 - 0.75) * (2.0 * PI / 3.0)).sin() + 1.0
+            },
+            Ease::BounceIn => 1.0 - Ease::BounceOut.apply(1.0 - t),
+            Ease::BounceOut => {
+                // Four parabolas, each bounce a quarter as high as the one before
+                let (n, d) = (7.5625, 2.75);
+                if t < 1.0 / d {
+                    n * t * t
+                } else if t < 2.0 / d {
+                    let t = t - 1.5 / d;
+                    n * t * t + 0.75
+                } else if t < 2.5 / d {
+                    let t = t - 2.25 / d;
+                    n * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / d;
+                    n * t * t + 0.984375
+                }
+            },
+        }
+    }
+}
+
+/// What a tween animates, and between which values
+#[derive(Debug, Clone)]
+pub enum Property {
+    Position { from : glm::Vec2, to : glm::Vec2 },
+    Colour { from : [f32; 3], to : [f32; 3] },
+    Scale { from : f32, to : f32 },
+    /// Radians, counter-clockwise
+    Rotation { from : f32, to : f32 },
+}
+
+/// A tree of animations. Sequences play their children one after the other, parallel groups all at once.
+#[derive(Debug, Clone)]
+pub enum Tween {
+    Animate { property : Property, duration : f32, ease : Ease },
+    /// Holds off whatever comes after it in a sequence
+    Wait(f32),
+    Sequence(Vec<Tween>),
+    Parallel(Vec<Tween>),
+}
+
+impl Tween {
+    pub fn new(property : Property, duration : f32, ease : Ease) -> Self {
+        Tween::Animate { property, duration, ease }
+    }
+
+    /// Seconds from start to finish
+    pub fn duration(&self) -> f32 {
+        match self {
+            Tween::Animate { duration, .. } => *duration,
+            Tween::Wait(duration) => *duration,
+            Tween::Sequence(tweens) => tweens.iter().map(|x| x.duration()).sum(),
+            Tween::Parallel(tweens) => tweens.iter().map(|x| x.duration()).fold(0.0, f32::max),
+        }
+    }
+
+    /// Sets every property as it is at time t. In a sequence, the latest started tween decides a
+    /// property, and the ones yet to start hold their properties at where they start from, which
+    /// also rewinds them when playing backwards.
+    fn apply(&self, t : f32, game : &mut Game, id : EntityId) {
+        match self {
+            Tween::Animate { property, duration, ease } => {
+                let s = ease.apply(if *duration > 0.0 { t / duration } else { 1.0 });
+                let i = id as usize;
+                match property {
+                    Property::Position { from, to } => {
+                        let p = from + (to - from) * s;
+                        game.positions[i].x = p.x;
+                        game.positions[i].y = p.y;
+                    },
+                    Property::Colour { from, to } => {
+                        for c in 0..3 {
+                            game.assets[i].colour[c] = from[c] + (to[c] - from[c]) * s;
+                        }
+                    },
+                    Property::Scale { from, to } => game.assets[i].scale = from + (to - from) * s,
+                    Property::Rotation { from, to } => game.orientations[i].angle = from + (to - from) * s,
+                }
+            },
+            Tween::Wait(_) => (),
+            Tween::Sequence(tweens) => {
+                let mut starts = Vec::with_capacity(tweens.len());
+                let mut start = 0.0;
+                for tween in tweens {
+                    starts.push(start);
+                    start += tween.duration();
+                }
+                let started = starts.iter().take_while(|x| t >= **x).count();
+                for tween in tweens[started..].iter().rev() {
+                    tween.apply(0.0, game, id);
+                }
+                for (tween, start) in tweens[..started].iter().zip(starts) {
+                    tween.apply(t - start, game, id);
+                }
+            },
+            Tween::Parallel(tweens) => {
+                for tween in tweens {
+                    tween.apply(t, game, id);
+                }
+            },
+        }
+    }
+}
+
+/// How a tween carries on once it reaches its end. None plays forever.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Repeat {
+    Once,
+    /// Starts over from the beginning, this many times in all
+    Loop(Option<u32>),
+    /// Plays backwards to the beginning, then forwards again. Each way counts as a time.
+    PingPong(Option<u32>),
+}
+
+/// Plays a tween on its entity.
+#[derive(Debug, Clone)]
+pub struct Tweening {
+    pub tween : Tween,
+    pub repeat : Repeat,
+    /// Passed along in the event sent when the tween finishes, to tell tweens apart
+    pub tag : u32,
+    /// Seconds since the tween started, counting every time through
+    pub elapsed : f32,
+}
+
+impl Default for Tweening {
+    fn default() -> Self {
+        Self::new(Tween::Wait(0.0), Repeat::Once, 0)
+    }
+}
+
+impl Tweening {
+    pub fn new(tween : Tween, repeat : Repeat, tag : u32) -> Self {
+        Self { tween, repeat, tag, elapsed : 0.0 }
+    }
+
+    /// How many times through the tween plays, None for forever
+    fn times(&self) -> Option<u32> {
+        match self.repeat {
+            Repeat::Once => Some(1),
+            Repeat::Loop(times) | Repeat::PingPong(times) => times,
+        }
+    }
+
+    /// Where in the tween it is, and whether it's done playing.
+    fn position(&self) -> (f32, bool) {
+        let duration = self.tween.duration();
+        if duration <= 0.0 {
+            return (0.0, true);
+        }
+        let round = (self.elapsed / duration).floor();
+        if let Some(times) = self.times() {
+            if round >= times as f32 {
+                let backwards = matches!(self.repeat, Repeat::PingPong(_)) && times % 2 == 0;
+                return (if backwards { 0.0 } else { duration }, true);
+            }
+        }
+        let t = self.elapsed - round * duration;
+        let backwards = matches!(self.repeat, Repeat::PingPong(_)) && round as u32 % 2 == 1;
+        (if backwards { duration - t } else { t }, false)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TweenEvent {
+    pub entity : EntityId,
+    pub tag : u32,
+}
+
+impl Game {
+    /// Moves every tween along and applies it. Finished tweens are applied at their end, lose
+    /// their flag and get an event.
+    pub(super) fn update_tweens(&mut self) {
+        self.tween_events.clear();
+        let dt = self.physics.time_step;
+        let tweening : Vec<EntityId> = self.entities.iter()
+            .filter(|x| x.components.contains(CompFlag::Twn))
+            .map(|x| x.id)
+            .collect();
+        for id in tweening {
+            let i = id as usize;
+            self.tweenings[i].elapsed += dt;
+            let (t, done) = self.tweenings[i].position();
+            // Taken out while applied, since it writes to the rest of the game
+            let tween = std::mem::replace(&mut self.tweenings[i].tween, Tween::Wait(0.0));
+            tween.apply(t, self, id);
+            self.tweenings[i].tween = tween;
+            if done {
+                self.entities[i].components.remove(CompFlag::Twn);
+                self.tween_events.push(TweenEvent { entity : id, tag : self.tweenings[i].tag });
+            }
+        }
+    }
+}
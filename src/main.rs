@@ -10,11 +10,12 @@ mod event;
 mod graphics;
 mod utils;
 use event::Event;
+use glutin::event::{ElementState, KeyboardInput, VirtualKeyCode, WindowEvent};
 use logic::CompFlag;
 use lazy_static::lazy_static;
 use rand::Rng;
 
-use crate::logic::{Position, Velocity};
+use crate::logic::{Attraction, Behaviour, BoundaryPolicy, Broadphase, ChecksumLog, Collider, Ease, Flocking, Fluid, Integrator, Lifetime, Machine, NavGrid, Navigation, ParticleEmitter, Position, Prefab, PrefabId, Property, Randomness, Repeat, Schedule, Shape, SpawnArea, Spawner, StateMachine, Steering, SweepAndPrune, SweepAxis, Target, Tilemap, Tileset, TimeControl, Tween, Tweening, Velocity, Wave, WorldBounds};
 
 lazy_static! {
     static ref ASSETS_PATH: Box<std::path::Path> = {
@@ -49,47 +50,133 @@ fn main() {
     let (mut game_graphics_tx, window_graphics_rx) = mpsc::sync_channel::<>(1);
 
     let window  = unsafe {window::Window::new(window_graphics_rx) };
-    let mut game = logic::Game::new();
-    let mut rng = rand::thread_rng();
-    for i in 0..10000 {
-        game.add_entity(CompFlag::all());
-        game.positions[i] = Position {
-            x : rng.gen::<f32>()*2.0-1.0,
-            y : rng.gen::<f32>()*2.0-1.0
-        };
-        game.velocities[i] = Velocity {
-            x : rng.gen::<f32>()/100.0-1.0/200.0,
-            y : rng.gen::<f32>()/100.0-1.0/200.0
-        }
-    }
-    game.positions[1] = Position {
-        x : 0.5, y : 1.5
-    };
-    game.positions[2] = Position {
-        x : 2.5, y : 1.5
+    let args : Vec<String> = std::env::args().collect();
+    let broadphase = if args.iter().any(|arg| arg == "--sweep") {
+        Broadphase::SweepAndPrune(SweepAndPrune::new(SweepAxis::X))
+    } else {
+        Broadphase::Grid
     };
-    game.velocities[2] = Velocity {
-        x : 0.1, y : 0.1
+    let mut game = logic::Game::with_broadphase(broadphase);
+    // Runs with the same seed play out the same
+    let seed = match args.iter().position(|arg| arg == "--seed").and_then(|i| args.get(i + 1)) {
+        Some(seed) => seed.parse().expect("--seed takes a whole number"),
+        None => rand::random(),
     };
+    println!("Seed: {}", seed);
+    game.random = Randomness::new(seed);
+    game.bounds = Some(WorldBounds::new(glm::vec2(-1.0, -1.0), glm::vec2(1.0, 1.0), BoundaryPolicy::Wrap));
+    if args.iter().any(|arg| arg == "--boids") {
+        flock_scene(&mut game);
+    } else if args.iter().any(|arg| arg == "--fluid") {
+        fluid_scene(&mut game);
+    } else if args.iter().any(|arg| arg == "--agents") {
+        agent_scene(&mut game);
+    } else if args.iter().any(|arg| arg == "--tiles") {
+        tile_scene(&mut game);
+    } else if args.iter().any(|arg| arg == "--maze") {
+        maze_scene(&mut game);
+    } else if args.iter().any(|arg| arg == "--particles") {
+        particle_scene(&mut game);
+    } else if args.iter().any(|arg| arg == "--fireworks") {
+        firework_scene(&mut game);
+    } else if args.iter().any(|arg| arg == "--tweens") {
+        tween_scene(&mut game);
+    } else if args.iter().any(|arg| arg == "--guards") {
+        guard_scene(&mut game);
+    } else if args.iter().any(|arg| arg == "--waves") {
+        wave_scene(&mut game);
+    } else {
+        drift_scene(&mut game, args.iter().any(|arg| arg == "--attract"));
+    }
 
+    if args.iter().any(|arg| arg == "--verlet") {
+        game.physics.integrator = Integrator::VelocityVerlet;
+    }
+    let print_states = args.iter().any(|arg| arg == "--print-states");
+    // Prints the world's checksum after every update, to compare runs with the same seed
+    if args.iter().any(|arg| arg == "--checksums") {
+        game.checksums = Some(ChecksumLog::new(600));
+    }
+    // Flat out instead of keeping pace with the clock, for measuring how fast updates are
+    let unpaced = args.iter().any(|arg| arg == "--unpaced");
+    // F5 saves the world here and F9 loads it back. Loading needs the scene the snapshot was saved
+    // from, since that's where the state machines come from.
+    let snapshot = std::path::PathBuf::from(match args.iter().position(|arg| arg == "--snapshot").and_then(|i| args.get(i + 1)) {
+        Some(path) => path.as_str(),
+        None => "world.snapshot",
+    });
     let mut i = 0;
     let mut now = std::time::Instant::now();
     let _ = std::thread::spawn(move || {
-
+        let mut time = TimeControl::default();
+        let mut last_tick = std::time::Instant::now();
         loop {
-            game.update(&mut game_graphics_tx);
-            i += 1;
+            for event in game_rx.try_iter() {
+                match event {
+                    Event::TogglePause => time.toggle_pause(),
+                    Event::Step => time.step(),
+                    Event::SpeedUp => time.set_scale(time.scale() * 2.0),
+                    Event::SlowDown => time.set_scale(time.scale() / 2.0),
+                    Event::ResetSpeed => time.set_scale(1.0),
+                    Event::SaveSnapshot => match game.save_snapshot_to(&snapshot) {
+                        Ok(()) => println!("Saved tick {} to {}", game.tick, snapshot.display()),
+                        Err(e) => println!("{}", e),
+                    },
+                    Event::LoadSnapshot => match game.load_snapshot_from(&snapshot) {
+                        Ok(()) => println!("Loaded tick {} from {}", game.tick, snapshot.display()),
+                        Err(e) => println!("{}", e),
+                    },
+                }
+            }
+            let real = last_tick.elapsed().as_secs_f32();
+            last_tick = std::time::Instant::now();
+            let mut ticks = time.ticks(real, game.physics.time_step);
+            if unpaced && !time.is_paused() {
+                ticks = 1;
+            }
+            if ticks == 0 {
+                // Nothing due yet, and the window keeps showing the last frame meanwhile
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            for _ in 0..ticks {
+                game.update(&mut game_graphics_tx);
+                if let Some((tick, checksum)) = game.checksums.as_ref().and_then(|x| x.latest()) {
+                    println!("Tick {}: {:016x}", tick, checksum);
+                }
+                i += 1;
+            }
             if now.elapsed().as_secs() >= 1 {
                 println!("{} rounds!", i);
+                if print_states {
+                    print!("{}", game.describe_states());
+                }
                 now = std::time::Instant::now();
                 i = 0;
             }
         }
     });
 
+    // Space pauses, S steps while paused, + and - speed time up and slow it down, 0 resets it,
+    // F5 saves a snapshot and F9 loads it
     let eh : window::EventHandler = Box::new(
         move |ev| {
             let send = match ev {
+                glutin::event::Event::WindowEvent {
+                    event : WindowEvent::KeyboardInput {
+                        input : KeyboardInput { state : ElementState::Pressed, virtual_keycode : Some(key), .. },
+                        ..
+                    },
+                    ..
+                } => match key {
+                    VirtualKeyCode::Space => Some(Event::TogglePause),
+                    VirtualKeyCode::S => Some(Event::Step),
+                    VirtualKeyCode::Equals | VirtualKeyCode::Plus | VirtualKeyCode::NumpadAdd => Some(Event::SpeedUp),
+                    VirtualKeyCode::Minus | VirtualKeyCode::NumpadSubtract => Some(Event::SlowDown),
+                    VirtualKeyCode::Key0 | VirtualKeyCode::Numpad0 => Some(Event::ResetSpeed),
+                    VirtualKeyCode::F5 => Some(Event::SaveSnapshot),
+                    VirtualKeyCode::F9 => Some(Event::LoadSnapshot),
+                    _ => None,
+                },
                 _ => None
             };
             if let Some(event) = send {
@@ -101,3 +188,410 @@ fn main() {
     unsafe { window.run(eh) };
 
 }
+
+/// Points drifting around at random, the stress test. With attract, every point pulls on every other one.
+fn drift_scene(game : &mut logic::Game, attract : bool) {
+    let mut components = CompFlag::Pos | CompFlag::Vel | CompFlag::Ass;
+    if attract {
+        components |= CompFlag::Mas;
+        game.attraction = Some(Attraction {
+            gravitational_constant : 1e-5,
+            softening : 0.05,
+            ..Attraction::default()
+        });
+    }
+    let point = game.add_prefab(Prefab::new(components));
+    scatter(game, point, 10000);
+}
+
+/// A spawner dropping count copies of the prefab all over the screen at once, heading every which way.
+fn scatter(game : &mut logic::Game, prefab : PrefabId, count : u32) {
+    let id = game.add_entity(CompFlag::Pos | CompFlag::Spw) as usize;
+    let mut spawner = Spawner::new(
+        prefab,
+        Schedule::Waves(vec![Wave { start : 0.0, count, interval : 0.0 }]),
+        SpawnArea::Rect { half_width : 1.0, half_height : 1.0 },
+        game.random.seed_for("scatter"),
+    );
+    spawner.velocity_spread = 0.3;
+    game.spawners[id] = spawner;
+}
+
+/// A few thousand boids, starting out scattered and heading every which way.
+fn flock_scene(game : &mut logic::Game) {
+    let flocking = Flocking::default();
+    game.set_cell_size(flocking.view_radius);
+    game.flocking = Some(flocking);
+    let boid = game.add_prefab(Prefab::new(CompFlag::Pos | CompFlag::Vel | CompFlag::Ass | CompFlag::Flk));
+    scatter(game, boid, 3000);
+}
+
+/// A block of water let go in the corner of a box, which collapses and sloshes to the other side.
+fn fluid_scene(game : &mut logic::Game) {
+    let fluid = Fluid::default();
+    game.set_cell_size(fluid.kernel_radius);
+    game.fluid = Some(fluid);
+    game.physics.gravity = glm::vec2(0.0, -1.0);
+    game.bounds = Some(WorldBounds::new(glm::vec2(-1.0, -1.0), glm::vec2(1.0, 1.0), BoundaryPolicy::Clamp));
+    for x in 0..30 {
+        for y in 0..40 {
+            let id = game.add_entity(CompFlag::Pos | CompFlag::Vel | CompFlag::Ass | CompFlag::Fld) as usize;
+            // Every other row is nudged sideways, so the columns don't stack up perfectly
+            game.positions[id] = Position {
+                x : -0.99 + x as f32 * 0.025 + (y % 2) as f32 * 0.005,
+                y : -0.99 + y as f32 * 0.025
+            };
+        }
+    }
+}
+
+/// Agents wandering between static pillars, and a hunter chasing the first of them, which the rest run from.
+fn agent_scene(game : &mut logic::Game) {
+    game.set_cell_size(0.2);
+    let mut rng = game.random.stream("agents");
+    for _ in 0..20 {
+        let id = game.add_entity(CompFlag::Pos | CompFlag::Col | CompFlag::Sta | CompFlag::Ass) as usize;
+        game.positions[id] = Position {
+            x : rng.gen::<f32>()*1.6-0.8,
+            y : rng.gen::<f32>()*1.6-0.8
+        };
+        game.colliders[id] = Collider { shape : Shape::Circle { radius : 0.05 }, ..Collider::default() };
+    }
+
+    let hunter = game.add_entity(CompFlag::Pos | CompFlag::Vel | CompFlag::Ass | CompFlag::Str);
+    let mut agents = Vec::new();
+    for _ in 0..300 {
+        let id = game.add_entity(CompFlag::Pos | CompFlag::Vel | CompFlag::Col | CompFlag::Ass | CompFlag::Str);
+        game.positions[id as usize] = Position {
+            x : rng.gen::<f32>()*2.0-1.0,
+            y : rng.gen::<f32>()*2.0-1.0
+        };
+        game.colliders[id as usize] = Collider { shape : Shape::Circle { radius : 0.01 }, ..Collider::default() };
+        game.steerings[id as usize] = Steering::new(2.0, 0.3)
+            .with(Behaviour::Wander { distance : 0.1, radius : 0.05, jitter : 20.0 }, 1.0)
+            .with(Behaviour::AvoidObstacles { look_ahead : 0.15 }, 3.0)
+            .with(Behaviour::Flee { target : Target::Entity(hunter), radius : 0.2 }, 2.0);
+        agents.push(id);
+    }
+    game.steerings[hunter as usize] = Steering::new(2.0, 0.35)
+        .with(Behaviour::Pursue(agents[0]), 1.0)
+        .with(Behaviour::AvoidObstacles { look_ahead : 0.15 }, 3.0);
+}
+
+/// Balls raining into a walled room with a few ledges, drawn from assets/textures/tiles.png.
+fn tile_scene(game : &mut logic::Game) {
+    // Stone, brick and grass block, water is only for show
+    let mut tileset = Tileset::new("/tiles.png", 4, 1);
+    tileset.blocking = vec![1, 2, 3];
+    let mut tilemap = Tilemap::new(40, 40, 0.05, glm::vec2(-1.0, -1.0), tileset);
+    for i in 0..40 {
+        tilemap.set(i, 0, 2);
+        tilemap.set(i, 1, 2);
+        tilemap.set(0, i, 2);
+        tilemap.set(39, i, 2);
+    }
+    // A pool sunk into the floor
+    for x in 1..39 {
+        tilemap.set(x, 2, if (10..30).contains(&x) { 4 } else { 2 });
+    }
+    for (left, right, y) in [(4, 14, 12), (24, 36, 18), (10, 26, 26)] {
+        for x in left..right {
+            tilemap.set(x, y, 1);
+            tilemap.set(x, y + 1, 3);
+        }
+    }
+    game.set_cell_size(0.1);
+    game.set_tilemap(Some(tilemap));
+    game.physics.gravity = glm::vec2(0.0, -1.0);
+    game.bounds = None;
+
+    let mut rng = game.random.stream("tiles");
+    for _ in 0..300 {
+        let id = game.add_entity(CompFlag::Pos | CompFlag::Vel | CompFlag::Col | CompFlag::Ass) as usize;
+        game.positions[id] = Position {
+            x : rng.gen::<f32>()*1.7-0.85,
+            y : rng.gen::<f32>()*0.3+0.6
+        };
+        game.colliders[id] = Collider { shape : Shape::Circle { radius : 0.015 }, restitution : 0.3, ..Collider::default() };
+    }
+}
+
+/// A random maze with a few walls knocked through. A crowd follows a flow field to the middle,
+/// and a courier takes the A* path from one corner to the opposite one.
+fn maze_scene(game : &mut logic::Game) {
+    const SIZE : usize = 33;
+    let mut tileset = Tileset::new("/tiles.png", 4, 1);
+    tileset.blocking = vec![2];
+    let mut tilemap = Tilemap::new(SIZE, SIZE, 2.0 / SIZE as f32, glm::vec2(-1.0, -1.0), tileset);
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            tilemap.set(x, y, 2);
+        }
+    }
+    // Depth first carving from one corner, over the cells at odd coordinates
+    let mut rng = game.random.stream("maze");
+    let mut stack = vec![(1, 1)];
+    tilemap.set(1, 1, 3);
+    while let Some(&(x, y)) = stack.last() {
+        let unvisited : Vec<(usize, usize)> = [(0, 2), (2, 0), (0, -2), (-2, 0)].iter()
+            .map(|(dx, dy)| ((x as i32 + dx) as usize, (y as i32 + dy) as usize))
+            .filter(|(nx, ny)| *nx > 0 && *ny > 0 && *nx < SIZE - 1 && *ny < SIZE - 1 && tilemap.tile(*nx, *ny) == Some(2))
+            .collect();
+        if unvisited.is_empty() {
+            stack.pop();
+            continue;
+        }
+        let (nx, ny) = unvisited[rng.gen_range(0..unvisited.len())];
+        tilemap.set((x + nx) / 2, (y + ny) / 2, 3);
+        tilemap.set(nx, ny, 3);
+        stack.push((nx, ny));
+    }
+    // Loops, so the crowd has more than one way to go
+    for _ in 0..40 {
+        let (x, y) = (rng.gen_range(1..SIZE - 1), rng.gen_range(1..SIZE - 1));
+        if (x + y) % 2 == 1 {
+            tilemap.set(x, y, 3);
+        }
+    }
+
+    let grid = NavGrid::from_tilemap(&tilemap);
+    game.set_cell_size(0.1);
+    game.set_tilemap(Some(tilemap));
+    game.bounds = None;
+    let mut navigation = Navigation::new(grid);
+    // Carved cells are at odd coordinates
+    let middle = (SIZE / 2) | 1;
+    let centre = navigation.grid.cell_centre(middle, middle);
+    let field = navigation.add_flow_field(&Position { x : centre.x, y : centre.y }).unwrap();
+
+    let agent = |game : &mut logic::Game, at : glm::Vec2| {
+        let id = game.add_entity(CompFlag::Pos | CompFlag::Vel | CompFlag::Col | CompFlag::Ass | CompFlag::Str) as usize;
+        game.positions[id] = Position { x : at.x, y : at.y };
+        game.colliders[id] = Collider { shape : Shape::Circle { radius : 0.012 }, restitution : 0.0, ..Collider::default() };
+        id
+    };
+    for _ in 0..200 {
+        let (x, y) = (rng.gen_range(0..SIZE / 2) * 2 + 1, rng.gen_range(0..SIZE / 2) * 2 + 1);
+        let id = agent(game, navigation.grid.cell_centre(x, y));
+        game.steerings[id] = Steering::new(2.0, 0.3).with(Behaviour::FollowFlow(field), 1.0);
+    }
+
+    let (start, end) = (navigation.grid.cell_centre(1, 1), navigation.grid.cell_centre(SIZE - 2, SIZE - 2));
+    let waypoints = navigation.grid.find_path(&Position { x : start.x, y : start.y }, &Position { x : end.x, y : end.y }).unwrap();
+    let courier = agent(game, start);
+    game.steerings[courier] = Steering::new(2.0, 0.4)
+        .with(Behaviour::FollowPath { waypoints, reach : 0.02, slowing_radius : 0.1 }, 1.0);
+    game.navigation = Some(navigation);
+}
+
+/// A fountain, a row of fires, and a comet flying across them trailing sparks.
+fn particle_scene(game : &mut logic::Game) {
+    let fountain = game.add_entity(CompFlag::Pos | CompFlag::Emt) as usize;
+    game.positions[fountain] = Position { x : 0.0, y : -0.8 };
+    let seed = game.random.seed_for("fountain");
+    let emitter = &mut game.emitters[fountain];
+    *emitter = ParticleEmitter::new(seed);
+    emitter.rate = 2000.0;
+    emitter.spread = 0.15;
+    emitter.speed = (1.2, 1.5);
+    emitter.lifetime = (1.5, 2.0);
+    emitter.colour = ([0.7, 0.9, 1.0], [0.1, 0.2, 0.8]);
+    emitter.size = (3.0, 6.0);
+    emitter.acceleration = glm::vec2(0.0, -1.2);
+    for i in 0..5 {
+        let fire = game.add_entity(CompFlag::Pos | CompFlag::Emt) as usize;
+        game.positions[fire] = Position { x : -0.8 + i as f32 * 0.4, y : 0.5 };
+        let seed = game.random.next_seed("fire");
+        let emitter = &mut game.emitters[fire];
+        *emitter = ParticleEmitter::new(seed);
+        emitter.rate = 400.0;
+        emitter.spread = 0.4;
+        emitter.speed = (0.05, 0.2);
+        emitter.lifetime = (0.4, 0.9);
+        emitter.colour = ([1.0, 0.9, 0.3], [0.4, 0.0, 0.0]);
+        emitter.size = (8.0, 1.0);
+        emitter.acceleration = glm::vec2(0.0, 0.3);
+    }
+    // Wraps around the edges, so it keeps passing through
+    let comet = game.add_entity(CompFlag::Pos | CompFlag::Vel | CompFlag::Ass | CompFlag::Emt) as usize;
+    game.positions[comet] = Position { x : -0.9, y : 0.0 };
+    game.velocities[comet] = Velocity { x : 0.5, y : 0.0 };
+    let seed = game.random.seed_for("comet");
+    let emitter = &mut game.emitters[comet];
+    *emitter = ParticleEmitter::new(seed);
+    emitter.rate = 600.0;
+    emitter.speed = (0.02, 0.1);
+    emitter.lifetime = (0.3, 0.6);
+    emitter.colour = ([1.0, 1.0, 1.0], [0.9, 0.5, 0.0]);
+    emitter.size = (4.0, 1.0);
+}
+
+/// Rockets going up, each bursting into sparks when its lifetime runs out. The bursts expire too,
+/// once they've given off their sparks.
+fn firework_scene(game : &mut logic::Game) {
+    game.bounds = None;
+    let colours = [[1.0, 0.3, 0.3], [0.3, 1.0, 0.4], [0.4, 0.6, 1.0], [1.0, 0.9, 0.3]];
+    let bursts : Vec<PrefabId> = colours.iter().map(|colour| {
+        let mut burst = Prefab::new(CompFlag::Pos | CompFlag::Emt | CompFlag::Ttl);
+        burst.emitter = ParticleEmitter::new(game.random.next_seed("burst"));
+        burst.emitter.rate = 4000.0;
+        burst.emitter.speed = (0.1, 0.4);
+        burst.emitter.lifetime = (0.8, 1.4);
+        burst.emitter.colour = (*colour, [0.2, 0.1, 0.0]);
+        burst.emitter.size = (4.0, 1.0);
+        burst.emitter.acceleration = glm::vec2(0.0, -0.3);
+        burst.lifetime = Lifetime::new(0.1);
+        game.add_prefab(burst)
+    }).collect();
+    let mut rng = game.random.stream("fireworks");
+    for _ in 0..40 {
+        let burst = bursts[rng.gen_range(0..bursts.len())];
+        launch_rocket(game, burst, glm::vec2(rng.gen::<f32>() * 1.6 - 0.8, rng.gen::<f32>() * 0.6), rng.gen_range(1.0..6.0));
+    }
+}
+
+/// A rocket trailing smoke, going straight up so it bursts at target after fuse seconds.
+fn launch_rocket(game : &mut logic::Game, burst : PrefabId, target : glm::Vec2, fuse : f32) {
+    const SPEED : f32 = 1.2;
+    let id = game.add_entity(CompFlag::Pos | CompFlag::Vel | CompFlag::Ass | CompFlag::Emt | CompFlag::Ttl) as usize;
+    // Long fuses start far below the screen, so the rockets don't all show up at once
+    game.positions[id] = Position { x : target.x, y : target.y - SPEED * fuse };
+    game.velocities[id] = Velocity { x : 0.0, y : SPEED };
+    game.lifetimes[id] = Lifetime::then(fuse, burst);
+    let seed = game.random.next_seed("rocket");
+    let emitter = &mut game.emitters[id];
+    *emitter = ParticleEmitter::new(seed);
+    emitter.rate = 150.0;
+    emitter.direction = -std::f32::consts::FRAC_PI_2;
+    emitter.spread = 0.3;
+    emitter.speed = (0.05, 0.1);
+    emitter.lifetime = (0.2, 0.5);
+    emitter.colour = ([1.0, 0.8, 0.5], [0.3, 0.3, 0.3]);
+    emitter.size = (3.0, 1.0);
+}
+
+/// A column of points going up and down for every easing curve, and a row of arrows spinning,
+/// growing and changing colour in a loop.
+fn tween_scene(game : &mut logic::Game) {
+    const EASES : [Ease; 11] = [
+        Ease::Linear, Ease::QuadIn, Ease::QuadOut, Ease::QuadInOut, Ease::CubicIn, Ease::CubicOut,
+        Ease::CubicInOut, Ease::ElasticIn, Ease::ElasticOut, Ease::BounceIn, Ease::BounceOut,
+    ];
+    for (column, ease) in EASES.iter().copied().enumerate() {
+        let x = -0.9 + column as f32 * 0.18;
+        for row in 0..5 {
+            let id = game.add_entity(CompFlag::Pos | CompFlag::Ass | CompFlag::Twn) as usize;
+            let from = glm::vec2(x + row as f32 * 0.02, -0.8);
+            let to = glm::vec2(from.x, 0.2);
+            // Each row lags a little behind the one before
+            game.tweenings[id] = Tweening::new(Tween::Sequence(vec![
+                Tween::Wait(row as f32 * 0.1),
+                Tween::Parallel(vec![
+                    Tween::new(Property::Position { from, to }, 2.0, ease),
+                    Tween::new(Property::Colour { from : [0.1, 0.2, 0.9], to : [0.9, 0.2, 0.1] }, 2.0, ease),
+                ]),
+            ]), Repeat::PingPong(None), column as u32);
+        }
+    }
+    for column in 0..8 {
+        let id = game.add_entity(CompFlag::Pos | CompFlag::Ass | CompFlag::Rot | CompFlag::Twn) as usize;
+        game.positions[id] = Position { x : -0.8 + column as f32 * 0.22, y : 0.6 };
+        game.tweenings[id] = Tweening::new(Tween::Sequence(vec![
+            Tween::new(Property::Rotation { from : 0.0, to : std::f32::consts::TAU }, 1.0, Ease::CubicInOut),
+            Tween::Parallel(vec![
+                Tween::new(Property::Scale { from : 1.0, to : 3.0 }, 0.8, Ease::ElasticOut),
+                Tween::new(Property::Colour { from : [1.0, 0.0, 0.0], to : [1.0, 0.8, 0.0] }, 0.8, Ease::QuadOut),
+            ]),
+            Tween::Wait(0.3 + column as f32 * 0.1),
+            Tween::Parallel(vec![
+                Tween::new(Property::Scale { from : 3.0, to : 1.0 }, 0.6, Ease::BounceOut),
+                Tween::new(Property::Colour { from : [1.0, 0.8, 0.0], to : [1.0, 0.0, 0.0] }, 0.6, Ease::Linear),
+            ]),
+        ]), Repeat::Loop(None), 100 + column as u32);
+    }
+}
+
+fn distance(game : &logic::Game, a : logic::EntityId, b : logic::EntityId) -> f32 {
+    let (pa, pb) = (&game.positions[a as usize], &game.positions[b as usize]);
+    ((pa.x - pb.x).powi(2) + (pa.y - pb.y).powi(2)).sqrt()
+}
+
+/// Guards wandering about until a thief running laps comes near, then chasing it until they're
+/// out of breath or it gets away, and catching their breath before patrolling again.
+fn guard_scene(game : &mut logic::Game) {
+    let thief = game.add_entity(CompFlag::Pos | CompFlag::Ass | CompFlag::Twn);
+    game.assets[thief as usize].colour = [1.0, 1.0, 0.0];
+    game.assets[thief as usize].scale = 2.0;
+    let corners = [glm::vec2(-0.7, -0.7), glm::vec2(0.7, -0.7), glm::vec2(0.7, 0.7), glm::vec2(-0.7, 0.7)];
+    game.tweenings[thief as usize] = Tweening::new(Tween::Sequence((0..4).map(|i| {
+        Tween::new(Property::Position { from : corners[i], to : corners[(i + 1) % 4] }, 3.0, Ease::QuadInOut)
+    }).collect()), Repeat::Loop(None), 0);
+
+    let mut machine = Machine::new("guard");
+    let patrol = machine.add_state("patrol");
+    let chase = machine.add_state("chase");
+    let rest = machine.add_state("rest");
+    machine.on_enter(patrol, |game, id| {
+        game.assets[id as usize].colour = [0.2, 0.3, 1.0];
+        game.steerings[id as usize] = Steering::new(1.0, 0.15)
+            .with(Behaviour::Wander { distance : 0.1, radius : 0.05, jitter : 10.0 }, 1.0);
+    });
+    machine.on_enter(chase, move |game, id| {
+        game.assets[id as usize].colour = [1.0, 0.1, 0.1];
+        game.steerings[id as usize] = Steering::new(2.0, 0.45).with(Behaviour::Pursue(thief), 1.0);
+    });
+    machine.on_enter(rest, |game, id| {
+        game.assets[id as usize].colour = [0.5, 0.5, 0.5];
+        let pos = &game.positions[id as usize];
+        let here = glm::vec2(pos.x, pos.y);
+        game.steerings[id as usize] = Steering::new(1.0, 0.15)
+            .with(Behaviour::Arrive { target : Target::Point(here), slowing_radius : 0.1 }, 1.0);
+    });
+    machine.add_transition(patrol, chase, move |game, id| distance(game, id, thief) < 0.3);
+    machine.add_transition(chase, rest, move |game, id| {
+        game.state_machines[id as usize].time_in_state() > 3.0 || distance(game, id, thief) > 0.5
+    });
+    machine.add_transition(rest, patrol, |game, id| game.state_machines[id as usize].time_in_state() > 2.0);
+    let machine = game.add_machine(machine);
+
+    let mut rng = game.random.stream("guards");
+    for _ in 0..30 {
+        let id = game.add_entity(CompFlag::Pos | CompFlag::Vel | CompFlag::Ass | CompFlag::Str | CompFlag::Fsm) as usize;
+        game.positions[id] = Position {
+            x : rng.gen::<f32>()*1.6-0.8,
+            y : rng.gen::<f32>()*1.6-0.8
+        };
+        game.state_machines[id] = StateMachine::new(machine);
+    }
+}
+
+/// Waves of short-lived raiders streaming in from a gate on the left, growing every wave, and a
+/// fountain in the middle keeping up to 100 sparks alive.
+fn wave_scene(game : &mut logic::Game) {
+    game.bounds = None;
+    let mut raider = Prefab::new(CompFlag::Pos | CompFlag::Vel | CompFlag::Ass | CompFlag::Ttl);
+    raider.velocity = Velocity { x : 0.4, y : 0.0 };
+    raider.asset.colour = [0.9, 0.3, 0.1];
+    raider.asset.scale = 1.5;
+    raider.lifetime = Lifetime::new(5.0);
+    let raider = game.add_prefab(raider);
+    let gate = game.add_entity(CompFlag::Pos | CompFlag::Spw) as usize;
+    game.positions[gate] = Position { x : -1.0, y : 0.0 };
+    let waves = (0..5).map(|i| Wave { start : i as f32 * 4.0, count : 20 * (i + 1), interval : 0.05 }).collect();
+    // A gate bent like a chevron, so raiders come in staggered
+    let gate_line = vec![glm::vec2(0.0, -0.6), glm::vec2(0.2, 0.0), glm::vec2(0.0, 0.6)];
+    let mut spawner = Spawner::new(raider, Schedule::Waves(waves), SpawnArea::Path(gate_line), game.random.seed_for("gate"));
+    spawner.velocity_spread = 0.05;
+    spawner.max_live = Some(150);
+    game.spawners[gate] = spawner;
+
+    let mut spark = Prefab::new(CompFlag::Pos | CompFlag::Vel | CompFlag::Ass | CompFlag::Ttl);
+    spark.asset.colour = [1.0, 1.0, 0.4];
+    spark.lifetime = Lifetime::new(1.5);
+    let spark = game.add_prefab(spark);
+    let fountain = game.add_entity(CompFlag::Pos | CompFlag::Spw) as usize;
+    let mut spawner = Spawner::new(spark, Schedule::Rate(200.0), SpawnArea::Circle { radius : 0.05 }, game.random.seed_for("spark fountain"));
+    spawner.velocity_spread = 0.3;
+    spawner.max_live = Some(100);
+    game.spawners[fountain] = spawner;
+}
@@ -4,13 +4,15 @@ use std::sync::mpsc::Receiver;
 use glutin::event::{Event, WindowEvent};
 use glutin::event_loop::ControlFlow;
 use crate::graphics::Renderer;
-use crate::logic::{Asset, Position};
+use crate::logic::Frame;
 
 pub struct Window {
     event_loop: Option<glutin::event_loop::EventLoop<()>>,
     context: glutin::ContextWrapper<glutin::PossiblyCurrent, glutin::window::Window>,
-    receiver : Receiver<Vec<(Asset, Position)>>,
+    receiver : Receiver<Frame>,
     renderer : Renderer,
+    /// Drawn again whenever the window needs it, since no frames come in while the game is paused
+    last_frame : Option<Frame>,
 }
 
 pub type EventHandler = Box<dyn FnMut(glutin::event::Event<()>) + Send + 'static>;
@@ -19,7 +21,7 @@ impl Window {
     ///
     /// unsafe, since calling twice on the same thread is likely to lead to serious trouble.
     /// Also, extremely stateful.
-    pub unsafe fn new(receiver : Receiver<Vec<(Asset, Position)>>) -> Window {
+    pub unsafe fn new(receiver : Receiver<Frame>) -> Window {
         let el = glutin::event_loop::EventLoop::new();
         let wb = glutin::window::WindowBuilder::new()
             .with_title("Hello world!")
@@ -53,20 +55,26 @@ impl Window {
             event_loop: Some(el),
             context: windowed_context,
             renderer : Renderer::new(screen_dimensions),
-            receiver
+            receiver,
+            last_frame : None,
         };
 
         res
     }
 
     unsafe fn render(&mut self) {
-        let rec = match self.receiver.try_recv() {
-            Ok(o) => o,
+        match self.receiver.try_recv() {
+            Ok(o) => self.last_frame = Some(o),
             Err(_) => return
         };
+        self.redraw();
+    }
 
-        self.renderer.render(&rec);
-        self.context.swap_buffers().unwrap();
+    unsafe fn redraw(&mut self) {
+        if let Some(frame) = &self.last_frame {
+            self.renderer.render(frame);
+            self.context.swap_buffers().unwrap();
+        }
     }
 
     unsafe fn update_screen_dimensions(&mut self, screen_dimensions: (u32, u32)) {
@@ -95,11 +103,12 @@ impl Window {
                                 physical_size.width,
                                 physical_size.height,
                             ));
+                            self.redraw();
                         }
                         WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
                         _ => event_handler(Event::WindowEvent { window_id, event }),
                     },
-                    Event::RedrawRequested(_) => {}
+                    Event::RedrawRequested(_) => self.redraw(),
                     Event::NewEvents(cs) => match cs {
                         glutin::event::StartCause::Poll => {
                             // Perform a render